@@ -0,0 +1,237 @@
+//! A stable JSON format for parsed chatlogs, so that other tools can make chatlogs without writing
+//! them in the play-script format that [`crate::parse_posts`] reads.
+//!
+//! A chatlog looks like this:
+//! ```json
+//! {
+//!   "version": 1,
+//!   "elements": [
+//!     { "type": "timestamp", "message": "Today at 4:20 PM" },
+//!     { "type": "post", "user": "EGGBUG", "message": "hello!\n" },
+//!     { "type": "post", "user": { "key": "GUEST", "name": "A Guest" }, "message": "hi!\n" }
+//!   ]
+//! }
+//! ```
+//!
+//! The `user` of a post (and each user in `mentions`) is either the key of a person, which is
+//! looked up in the config when the chatlog is read, or an object containing all of the fields of
+//! the person, which is used as-is. `mentions` and `link_previews` may be left out. If `mentions`
+//! is left out, it is found from the message, like when parsing a chatlog.
+//!
+//! Messages are used exactly as written, so quirks and at-macros aren't applied to them.
+
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, markdown, ChatlogElement, LinkPreview, User};
+
+/// The version of the chatlog format written by [`export_elements`]. This is only changed when
+/// the format changes in a way that older versions of cohoard couldn't read.
+pub const VERSION: u32 = 1;
+
+/// A versioned, serializable chatlog. See the [module documentation](self) for the format.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chatlog {
+    pub version: u32,
+    pub elements: Vec<Element>,
+}
+
+/// An element of a [`Chatlog`]. This is the same as a [`ChatlogElement`], except that users can
+/// be referred to by their key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+#[serde(rename_all = "lowercase")]
+pub enum Element {
+    Timestamp {
+        message: String,
+    },
+    Post {
+        user: UserRef,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mentions: Option<Vec<UserRef>>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        link_previews: Vec<LinkPreview>,
+    },
+}
+
+/// A user in a [`Chatlog`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UserRef {
+    /// The key of a person, which is looked up in the config.
+    Key(String),
+    /// All of the fields of a person.
+    User(User),
+}
+
+/// How [`export_elements`] writes users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserStyle {
+    /// Write every field of each user, so that the chatlog can be rendered without the config.
+    Resolved,
+    /// Write only the key of each user, so that the users are looked up in the config when the
+    /// chatlog is rendered.
+    Keys,
+}
+
+impl UserRef {
+    fn new(user: &User, style: UserStyle) -> UserRef {
+        match (style, user.get_str("key")) {
+            (UserStyle::Keys, Some(key)) => UserRef::Key(key.to_string()),
+            _ => UserRef::User(user.clone()),
+        }
+    }
+
+    fn resolve(self, config: &Config) -> User {
+        match self {
+            UserRef::Key(key) => config.get_user(&key),
+            UserRef::User(user) => user,
+        }
+    }
+}
+
+/// Turn the elements of a parsed chatlog (usually from [`crate::parse_posts`]) into a [`Chatlog`],
+/// which can then be serialized as JSON.
+pub fn export_elements(elements: &[ChatlogElement], style: UserStyle) -> Chatlog {
+    let elements = elements
+        .iter()
+        .map(|element| match element {
+            ChatlogElement::Timestamp { message } => Element::Timestamp {
+                message: message.clone(),
+            },
+            ChatlogElement::Post {
+                user,
+                message,
+                mentions,
+                link_previews,
+            } => Element::Post {
+                user: UserRef::new(user, style),
+                message: message.clone(),
+                mentions: Some(
+                    mentions
+                        .iter()
+                        .map(|user| UserRef::new(user, style))
+                        .collect(),
+                ),
+                link_previews: link_previews.clone(),
+            },
+        })
+        .collect();
+    Chatlog {
+        version: VERSION,
+        elements,
+    }
+}
+
+/// Read a chatlog written as JSON, looking up any users referred to by key in the config.
+///
+/// This is an error if the JSON isn't a chatlog, or if it was written by a newer version of
+/// cohoard than this one.
+pub fn import_elements(config: &Config, json: &str) -> Result<Vec<ChatlogElement>, Box<dyn Error>> {
+    // Check the version before anything else, so that a chatlog from a newer version gets a
+    // better error than whatever part of it this version doesn't understand.
+    #[derive(Deserialize)]
+    struct Version {
+        version: Option<u32>,
+    }
+    let Version { version } = serde_json::from_str(json)
+        .map_err(|err| format!("Couldn't read the chatlog JSON: {}", err))?;
+    match version {
+        Some(VERSION) => (),
+        Some(version) => {
+            return Err(format!(
+                "The chatlog is version {}, but this version of cohoard only reads version {}",
+                version, VERSION
+            )
+            .into())
+        }
+        None => return Err("The chatlog JSON doesn't have a version".into()),
+    }
+
+    let chatlog: Chatlog = serde_json::from_str(json)
+        .map_err(|err| format!("Couldn't read the chatlog JSON: {}", err))?;
+    Ok(resolve_elements(config, chatlog.elements))
+}
+
+// Turn the elements of a chatlog back into `ChatlogElement`s.
+fn resolve_elements(config: &Config, elements: Vec<Element>) -> Vec<ChatlogElement> {
+    elements
+        .into_iter()
+        .map(|element| match element {
+            Element::Timestamp { message } => ChatlogElement::Timestamp { message },
+            Element::Post {
+                user,
+                message,
+                mentions,
+                link_previews,
+            } => {
+                let mentions = match mentions {
+                    Some(mentions) => mentions
+                        .into_iter()
+                        .map(|user| user.resolve(config))
+                        .collect(),
+                    None => markdown::find_mentions(config, &message),
+                };
+                ChatlogElement::Post {
+                    user: user.resolve(config),
+                    message,
+                    mentions,
+                    link_previews,
+                }
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_export_and_import() {
+    let config = crate::config::load_config(
+        "people:\n  - key: EGGBUG\n    name: eggbug\n  - key: BUGEGG\n    name: bugegg\n",
+    )
+    .unwrap();
+    let elements = crate::parse_posts(
+        &config,
+        "@ Today\nEGGBUG: hi @BUGEGG\nGUEST: hello\n".to_string(),
+    )
+    .unwrap();
+
+    let json = serde_json::to_string(&export_elements(&elements, UserStyle::Keys)).unwrap();
+    assert_eq!(
+        json,
+        r#"{"version":1,"elements":[{"type":"timestamp","message":"Today"},{"type":"post","user":"EGGBUG","message":"hi @BUGEGG\n","mentions":["BUGEGG"]},{"type":"post","user":"GUEST","message":"hello\n","mentions":[]}]}"#
+    );
+    let imported = import_elements(&config, &json).unwrap();
+    assert_eq!(
+        serde_json::to_value(&imported).unwrap(),
+        serde_json::to_value(&elements).unwrap()
+    );
+
+    // Resolved users don't need the config.
+    let json = serde_json::to_string(&export_elements(&elements, UserStyle::Resolved)).unwrap();
+    let imported = import_elements(&Config::default(), &json).unwrap();
+    assert_eq!(
+        serde_json::to_value(&imported).unwrap(),
+        serde_json::to_value(&elements).unwrap()
+    );
+
+    // Mentions are found from the message if they're left out.
+    let imported = import_elements(
+        &config,
+        r#"{"version":1,"elements":[{"type":"post","user":{"key":"NEW","name":"new"},"message":"@EGGBUG"}]}"#,
+    )
+    .unwrap();
+    let ChatlogElement::Post { user, mentions, .. } = &imported[0] else {
+        panic!("expected a post");
+    };
+    assert_eq!(user.get_str("name"), Some("new"));
+    assert_eq!(mentions[0].get_str("name"), Some("eggbug"));
+
+    let err = import_elements(&config, r#"{"version":2,"elements":[]}"#).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The chatlog is version 2, but this version of cohoard only reads version 1"
+    );
+    assert!(import_elements(&config, r#"{"elements":[]}"#).is_err());
+}
@@ -0,0 +1,389 @@
+use std::error::Error;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+use tera::{Context, Tera};
+
+use crate::{config::Config, User};
+
+lazy_static! {
+    // Matches `@KEY.field|filter` at-macros, along with an optional leading backslash that escapes
+    // the macro. Keys can contain the same characters as the speaker names in a chatlog.
+    static ref AT_MACRO_RE: Regex = Regex::new(
+        r"(?P<escape>\\)?@(?P<key>[\p{Alphabetic}\p{N}]+)\.(?P<field>\w+)(?P<filters>(?:\|\w+)*)"
+    )
+    .unwrap();
+    // Matches a `{#-at-macro ... at-macro-#}` block in a template.
+    static ref WRAPPER_RE: Regex = Regex::new(r"(?s)\{#-at-macro(?P<wrapper>.*?)at-macro-#\}").unwrap();
+}
+
+const WRAPPER_TEMPLATE_NAME: &str = "at-macro.html";
+
+/// A transform applied to the value of an at-macro, written after the macro with a `|`, as in
+/// `@KARKAT.handle|initials`. Filters are applied from left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AtMacroFilter {
+    /// Uppercase the value.
+    Upper,
+    /// Lowercase the value.
+    Lower,
+    /// Take the first letter of each word in the value, counting camelCase humps as words, and
+    /// uppercase them. For example, "carcinoGeneticist" becomes "CG".
+    Initials,
+    /// Show a square of the color given by the value instead of the value itself. Since this turns
+    /// the value into markup, it must be the last filter.
+    Swatch,
+}
+
+impl AtMacroFilter {
+    fn from_name(name: &str) -> Option<AtMacroFilter> {
+        match name {
+            "upper" => Some(AtMacroFilter::Upper),
+            "lower" => Some(AtMacroFilter::Lower),
+            "initials" => Some(AtMacroFilter::Initials),
+            "swatch" => Some(AtMacroFilter::Swatch),
+            _ => None,
+        }
+    }
+}
+
+// Get the text that an at-macro for a field expands to. Only strings, numbers, and booleans can be
+// expanded, since there isn't a good way to write lists or maps as text.
+fn field_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(string) => Some(string.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(boolean) => Some(boolean.to_string()),
+        Value::Null | Value::Array(_) | Value::Object(_) => None,
+    }
+}
+
+/// Get the initials of some text. See [`AtMacroFilter::Initials`].
+pub(crate) fn initials(text: &str) -> String {
+    let mut initials = String::new();
+    let mut prev: Option<char> = None;
+    for c in text.chars() {
+        let starts_word = !prev.is_some_and(|prev| prev.is_alphanumeric());
+        let starts_hump = c.is_uppercase() && prev.is_some_and(|prev| prev.is_lowercase());
+        if c.is_alphanumeric() && (starts_word || starts_hump) {
+            initials.extend(c.to_uppercase());
+        }
+        prev = Some(c);
+    }
+    initials
+}
+
+// Apply filters to the value of an at-macro, returning the filtered value along with the HTML for
+// it. The HTML is the escaped value, unless the value was turned into a swatch.
+fn apply_filters(value: &str, filters: &[AtMacroFilter]) -> (String, String) {
+    let mut value = value.to_string();
+    for filter in filters {
+        match filter {
+            AtMacroFilter::Upper => value = value.to_uppercase(),
+            AtMacroFilter::Lower => value = value.to_lowercase(),
+            AtMacroFilter::Initials => value = initials(&value),
+            AtMacroFilter::Swatch => {
+                let html = format!(
+                    "<span class=\"at-macro-swatch\" style=\"display: inline-block; width: 0.8em; \
+                     height: 0.8em; border-radius: 2px; background: {};\"></span>",
+                    tera::escape_html(&value)
+                );
+                return (value, html);
+            }
+        }
+    }
+    let html = tera::escape_html(&value);
+    (value, html)
+}
+
+/// The markup that expanded at-macros are wrapped in.
+///
+/// By default, an at-macro becomes a `<span class="at-macro at-macro-KEY at-macro-KEY-field">`
+/// containing the HTML-escaped value of the field. Templates can replace this by including an
+/// `{#-at-macro ... at-macro-#}` block, which contains a Tera template that is rendered for every
+/// at-macro with the variables `user`, `key`, `field`, `value`, and `html`. For example:
+/// ```no_compile
+/// {#-at-macro <b style="color: {{ user.color }}">{{ value }}</b> at-macro-#}
+/// ```
+/// The value is HTML-escaped in the block as well, unless it is passed through the `safe` filter.
+/// `html` is what the default wrapper would put inside the span: the escaped value, or the swatch
+/// if the `swatch` filter was used.
+#[derive(Default)]
+pub(crate) struct AtMacroWrapper(Option<Tera>);
+
+impl AtMacroWrapper {
+    /// Get the wrapper from the `{#-at-macro ... at-macro-#}` block of a template, if it has one.
+    pub(crate) fn from_template(template: &str) -> tera::Result<AtMacroWrapper> {
+        let Some(captures) = WRAPPER_RE.captures(template) else {
+            return Ok(AtMacroWrapper::default());
+        };
+
+        let mut tera = Tera::default();
+        tera.add_raw_template(WRAPPER_TEMPLATE_NAME, captures["wrapper"].trim())?;
+        Ok(AtMacroWrapper(Some(tera)))
+    }
+
+    fn wrap(
+        &self,
+        user: &User,
+        key: &str,
+        field: &str,
+        value: &str,
+        html: &str,
+    ) -> tera::Result<String> {
+        let Some(tera) = &self.0 else {
+            return Ok(format!(
+                "<span class=\"at-macro at-macro-{} at-macro-{}-{}\">{}</span>",
+                key, key, field, html
+            ));
+        };
+
+        let mut context = Context::new();
+        context.insert("user", user);
+        context.insert("key", key);
+        context.insert("field", field);
+        context.insert("value", value);
+        context.insert("html", html);
+        tera.render(WRAPPER_TEMPLATE_NAME, &context)
+    }
+}
+
+/// Expand the `@KEY.field` at-macros in a message into the value of `field` for the person with
+/// the key `KEY`, wrapped in the markup given by `wrapper`. The value can be transformed with
+/// filters, as in `@KEY.field|upper`. See [`AtMacroFilter`] for the list of filters.
+///
+/// A backslash before an at-macro, as in `\@KEY.field`, escapes it, leaving the literal text
+/// `@KEY.field`. At-macros directly after a letter or number, like in email addresses, are also
+/// left alone. At-macros whose key or field doesn't exist are left as-is, and unknown filters are
+/// left as text, unless the config has `strict_at_macros` set, in which case they are an error.
+pub(crate) fn convert_at_macros(
+    config: &Config,
+    wrapper: &AtMacroWrapper,
+    message: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut output = String::with_capacity(message.len());
+    let mut last_end = 0;
+
+    for captures in AT_MACRO_RE.captures_iter(message) {
+        let whole = captures.get(0).unwrap();
+        let follows_word = message[..whole.start()]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        if follows_word {
+            continue;
+        }
+
+        output.push_str(&message[last_end..whole.start()]);
+        last_end = whole.end();
+
+        if captures.name("escape").is_some() {
+            output.push_str(&whole.as_str()[1..]);
+            continue;
+        }
+
+        let key = &captures["key"];
+        let field = &captures["field"];
+        let Some(user) = config.people.get(key) else {
+            if config.strict_at_macros {
+                return Err(format!(
+                    "Unknown at-macro \"{}\": there is no person with the key \"{}\"",
+                    whole.as_str(),
+                    key
+                )
+                .into());
+            }
+            output.push_str(whole.as_str());
+            continue;
+        };
+        let value = match user.fields.get(field).map(field_text) {
+            Some(Some(value)) => value,
+            Some(None) | None if !config.strict_at_macros => {
+                output.push_str(whole.as_str());
+                continue;
+            }
+            Some(None) => {
+                return Err(format!(
+                    "Unknown at-macro \"{}\": the field \"{}\" of {} isn't text or a number",
+                    whole.as_str(),
+                    field,
+                    key
+                )
+                .into());
+            }
+            None => {
+                return Err(format!(
+                    "Unknown at-macro \"{}\": {} has no field called \"{}\"",
+                    whole.as_str(),
+                    key,
+                    field
+                )
+                .into());
+            }
+        };
+
+        // Only the filters up to the first unknown one are part of the at-macro. The rest are left
+        // as text, so that something like "@KEY.field|not a filter" still works.
+        let mut filters = vec![];
+        last_end = captures.name("field").unwrap().end();
+        for name in captures["filters"].split('|').skip(1) {
+            let filter = match (filters.last(), AtMacroFilter::from_name(name)) {
+                (Some(AtMacroFilter::Swatch), _) => Err("nothing can come after swatch"),
+                (_, None) => Err("there is no filter with that name"),
+                (_, Some(filter)) => Ok(filter),
+            };
+            match filter {
+                Ok(filter) => {
+                    filters.push(filter);
+                    last_end += name.len() + 1;
+                }
+                Err(reason) if config.strict_at_macros => {
+                    return Err(format!(
+                        "Unknown at-macro filter \"{}\" in \"{}\": {}",
+                        name,
+                        whole.as_str(),
+                        reason
+                    )
+                    .into());
+                }
+                Err(_) => break,
+            }
+        }
+
+        let (value, html) = apply_filters(&value, &filters);
+        output.push_str(&wrapper.wrap(user, key, field, &value, &html)?);
+    }
+
+    output.push_str(&message[last_end..]);
+    Ok(output)
+}
+
+#[cfg(test)]
+fn test_config(strict: bool) -> Config {
+    let config = r##"people:
+    - key: JUICE
+      name: Juice
+    - key: TEN
+      handle: Ten
+    - key: KARKAT
+      handle: carcinoGeneticist
+      color: "#626262"
+    - key: AG2
+      name: <b>Agent 2</b>
+      followers: 12
+      badges: [mod, bot]
+  "##;
+    let mut config = crate::config::load_config(config).unwrap();
+    config.strict_at_macros = strict;
+    config
+}
+
+#[test]
+fn test_at_macro() {
+    let config = test_config(false);
+    let convert =
+        |message: &str| convert_at_macros(&config, &AtMacroWrapper::default(), message).unwrap();
+
+    let message = "@JUICE.name started pestering @TEN.handle";
+    let expected = "<span class=\"at-macro at-macro-JUICE at-macro-JUICE-name\">Juice</span> started pestering <span class=\"at-macro at-macro-TEN at-macro-TEN-handle\">Ten</span>";
+    assert_eq!(convert(message), expected);
+
+    // Values are escaped, and keys can have digits.
+    assert_eq!(
+        convert("hi @AG2.name"),
+        "hi <span class=\"at-macro at-macro-AG2 at-macro-AG2-name\">&lt;b&gt;Agent 2&lt;&#x2F;b&gt;</span>"
+    );
+
+    // Numbers are expanded too, but lists aren't.
+    assert_eq!(
+        convert("@AG2.followers @AG2.badges"),
+        "<span class=\"at-macro at-macro-AG2 at-macro-AG2-followers\">12</span> @AG2.badges"
+    );
+
+    // Escaped macros, email addresses, and unknown keys and fields are left as-is.
+    assert_eq!(
+        convert(r"\@JUICE.name juice@JUICE.name @NOBODY.name @TEN.color"),
+        "@JUICE.name juice@JUICE.name @NOBODY.name @TEN.color"
+    );
+}
+
+#[test]
+fn test_at_macro_strict() {
+    let config = test_config(true);
+    let convert = |message: &str| {
+        convert_at_macros(&config, &AtMacroWrapper::default(), message).map_err(|e| e.to_string())
+    };
+
+    assert_eq!(
+        convert("@NOBODY.name"),
+        Err(
+            "Unknown at-macro \"@NOBODY.name\": there is no person with the key \"NOBODY\""
+                .to_string()
+        )
+    );
+    assert_eq!(
+        convert("@TEN.color"),
+        Err("Unknown at-macro \"@TEN.color\": TEN has no field called \"color\"".to_string())
+    );
+    assert_eq!(
+        convert("@AG2.badges"),
+        Err(
+            "Unknown at-macro \"@AG2.badges\": the field \"badges\" of AG2 isn't text or a number"
+                .to_string()
+        )
+    );
+    // Escaped macros aren't errors.
+    assert_eq!(convert(r"\@NOBODY.name"), Ok("@NOBODY.name".to_string()));
+}
+
+#[test]
+fn test_at_macro_wrapper() {
+    let config = test_config(false);
+    let template = r#"<div>{#-at-macro
+        <b data-key="{{ key }}.{{ field }}">{{ value }}</b>
+    at-macro-#}</div>"#;
+    let wrapper = AtMacroWrapper::from_template(template).unwrap();
+    assert_eq!(
+        convert_at_macros(&config, &wrapper, "@JUICE.name and @AG2.name").unwrap(),
+        "<b data-key=\"JUICE.name\">Juice</b> and <b data-key=\"AG2.name\">&lt;b&gt;Agent 2&lt;&#x2F;b&gt;</b>"
+    );
+}
+
+#[test]
+fn test_at_macro_filters() {
+    let config = test_config(false);
+    let convert =
+        |message: &str| convert_at_macros(&config, &AtMacroWrapper::default(), message).unwrap();
+
+    assert_eq!(
+        convert("@KARKAT.handle [@KARKAT.handle|initials] began trolling @JUICE.name|upper!"),
+        "<span class=\"at-macro at-macro-KARKAT at-macro-KARKAT-handle\">carcinoGeneticist</span> \
+         [<span class=\"at-macro at-macro-KARKAT at-macro-KARKAT-handle\">CG</span>] began trolling \
+         <span class=\"at-macro at-macro-JUICE at-macro-JUICE-name\">JUICE</span>!"
+    );
+    assert_eq!(
+        convert("@KARKAT.color|swatch"),
+        "<span class=\"at-macro at-macro-KARKAT at-macro-KARKAT-color\"><span class=\"at-macro-swatch\" \
+         style=\"display: inline-block; width: 0.8em; height: 0.8em; border-radius: 2px; \
+         background: #626262;\"></span></span>"
+    );
+    // Unknown filters, and anything after a swatch, are left as text.
+    assert_eq!(
+        convert("@JUICE.name|lower|nope|upper @KARKAT.color|swatch|upper"),
+        "<span class=\"at-macro at-macro-JUICE at-macro-JUICE-name\">juice</span>|nope|upper \
+         <span class=\"at-macro at-macro-KARKAT at-macro-KARKAT-color\"><span class=\"at-macro-swatch\" \
+         style=\"display: inline-block; width: 0.8em; height: 0.8em; border-radius: 2px; \
+         background: #626262;\"></span></span>|upper"
+    );
+
+    assert_eq!(initials("Karkat Vantas"), "KV");
+    assert_eq!(initials("twinArmageddons"), "TA");
+
+    let strict = test_config(true);
+    assert_eq!(
+        convert_at_macros(&strict, &AtMacroWrapper::default(), "@JUICE.name|nope")
+            .map_err(|e| e.to_string()),
+        Err("Unknown at-macro filter \"nope\" in \"@JUICE.name|nope\": there is no filter with that name".to_string())
+    );
+}
@@ -0,0 +1,545 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value;
+
+lazy_static! {
+    // Matches a CSS color function, like `rgb(1, 2, 3)` or `var(--color)`.
+    static ref FUNCTION_RE: Regex =
+        Regex::new(r"^(?P<name>[a-zA-Z-]+)\((?P<args>[^()]*(?:\([^()]*\)[^()]*)*)\)$").unwrap();
+}
+
+// The CSS named colors, from https://www.w3.org/TR/css-color-4/#named-colors.
+const NAMED_COLORS: &[(&str, u32)] = &[
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+// CSS keywords and functions which are valid colors, but which can't be turned into RGB without
+// knowing where the color is used. These are left as-is.
+const OPAQUE_KEYWORDS: &[&str] = &["currentcolor", "inherit", "initial", "unset", "revert"];
+const OPAQUE_FUNCTIONS: &[&str] = &[
+    "var",
+    "hwb",
+    "lab",
+    "lch",
+    "oklab",
+    "oklch",
+    "color",
+    "color-mix",
+];
+
+// The colors given to speakers who aren't in the config, which are Discord's role colors. See
+// `auto_color`.
+const AUTO_COLOR_PALETTE: &[u32] = &[
+    0x1abc9c, 0x2ecc71, 0x3498db, 0x9b59b6, 0xe91e63, 0xf1c40f, 0xe67e22, 0xe74c3c, 0x11806a,
+    0x1f8b4c, 0x206694, 0x71368a, 0xad1457, 0xc27c0e, 0xa84300, 0x992d22,
+];
+
+// The lowest contrast ratio that an automatic color can have against the background. This is the
+// WCAG minimum for large text, which usernames usually are.
+const AUTO_COLOR_MIN_CONTRAST: f64 = 3.0;
+
+// The suffixes of the fields derived from each color field. See [`derived_fields`].
+const RGB_SUFFIX: &str = "_rgb";
+const CONTRAST_SUFFIX: &str = "_contrast";
+const FADED_SUFFIX: &str = "_faded";
+
+/// An sRGB color with an alpha channel between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: f64,
+}
+
+impl Color {
+    fn from_hex_number(hex: u32) -> Color {
+        Color {
+            r: (hex >> 16) as u8,
+            g: (hex >> 8) as u8,
+            b: hex as u8,
+            a: 1.0,
+        }
+    }
+
+    /// Parse a CSS hex color, named color, or `rgb()`, `rgba()`, `hsl()`, or `hsla()` color.
+    /// Returns None for anything else, including colors which [`normalize_color`] leaves as-is.
+    pub fn parse(color: &str) -> Option<Color> {
+        let color = color.trim();
+        if let Some(hex) = color.strip_prefix('#') {
+            return Color::parse_hex(hex);
+        }
+
+        let lowercase = color.to_ascii_lowercase();
+        if lowercase == "transparent" {
+            return Some(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0.0,
+            });
+        }
+        if let Some((_, hex)) = NAMED_COLORS.iter().find(|(name, _)| *name == lowercase) {
+            return Some(Color::from_hex_number(*hex));
+        }
+
+        let captures = FUNCTION_RE.captures(&lowercase)?;
+        let args: Vec<&str> = captures["args"]
+            .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .collect();
+        let (channels, alpha) = match args.as_slice() {
+            [a, b, c] => ([*a, *b, *c], 1.0),
+            [a, b, c, alpha] => ([*a, *b, *c], parse_fraction(alpha)?),
+            _ => return None,
+        };
+        let [r, g, b] = match &captures["name"] {
+            "rgb" | "rgba" => [
+                parse_channel(channels[0])?,
+                parse_channel(channels[1])?,
+                parse_channel(channels[2])?,
+            ],
+            "hsl" | "hsla" => hsl_to_rgb(
+                parse_hue(channels[0])?,
+                parse_percentage(channels[1])?,
+                parse_percentage(channels[2])?,
+            ),
+            _ => return None,
+        };
+        Some(Color { r, g, b, a: alpha })
+    }
+
+    fn parse_hex(hex: &str) -> Option<Color> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap();
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+        let (r, g, b, a) = match hex.len() {
+            3 => (digit(0) * 17, digit(1) * 17, digit(2) * 17, 255),
+            4 => (digit(0) * 17, digit(1) * 17, digit(2) * 17, digit(3) * 17),
+            6 => (byte(0), byte(2), byte(4), 255),
+            8 => (byte(0), byte(2), byte(4), byte(6)),
+            _ => return None,
+        };
+        Some(Color {
+            r,
+            g,
+            b,
+            a: a as f64 / 255.0,
+        })
+    }
+
+    /// Write the color as lowercase `#rrggbb`, or `#rrggbbaa` if it is at all transparent.
+    pub fn to_hex(self) -> String {
+        let alpha = (self.a.clamp(0.0, 1.0) * 255.0).round() as u8;
+        if alpha == 255 {
+            format!("#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", self.r, self.g, self.b, alpha)
+        }
+    }
+
+    /// The relative luminance of the color, ignoring the alpha channel, as defined by WCAG.
+    pub fn luminance(self) -> f64 {
+        let linear = |channel: u8| {
+            let channel = channel as f64 / 255.0;
+            if channel <= 0.03928 {
+                channel / 12.92
+            } else {
+                ((channel + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// The WCAG contrast ratio between two colors, from 1 (no contrast) to 21 (black on white).
+    pub fn contrast(self, other: Color) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        (a.max(b) + 0.05) / (a.min(b) + 0.05)
+    }
+
+    /// Black or white, whichever is more readable on top of this color.
+    pub fn contrast_text(self) -> Color {
+        let black = Color::from_hex_number(0x000000);
+        let white = Color::from_hex_number(0xffffff);
+        if self.contrast(black) >= self.contrast(white) {
+            black
+        } else {
+            white
+        }
+    }
+}
+
+/// Pick a color for a speaker who isn't in the config, which is readable on top of the given
+/// background. The color is picked from a palette with a hash of the speaker's key, so each speaker
+/// always gets the same color, and different speakers usually get different colors.
+pub(crate) fn auto_color(key: &str, background: Color) -> Color {
+    let readable: Vec<Color> = AUTO_COLOR_PALETTE
+        .iter()
+        .map(|hex| Color::from_hex_number(*hex))
+        .filter(|color| color.contrast(background) >= AUTO_COLOR_MIN_CONTRAST)
+        .collect();
+    if readable.is_empty() {
+        return background.contrast_text();
+    }
+
+    // FNV-1a, since it's simple and gives the same hash on every platform and Rust version.
+    let hash = key.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    });
+    readable[hash as usize % readable.len()]
+}
+
+// Parse an rgb() channel, which is either a number from 0 to 255 or a percentage.
+fn parse_channel(channel: &str) -> Option<u8> {
+    let value = match channel.strip_suffix('%') {
+        Some(percentage) => percentage.parse::<f64>().ok()? * 2.55,
+        None => channel.parse::<f64>().ok()?,
+    };
+    Some(value.clamp(0.0, 255.0).round() as u8)
+}
+
+// Parse an alpha value, which is either a number from 0 to 1 or a percentage.
+fn parse_fraction(fraction: &str) -> Option<f64> {
+    let value = match fraction.strip_suffix('%') {
+        Some(percentage) => percentage.parse::<f64>().ok()? / 100.0,
+        None => fraction.parse::<f64>().ok()?,
+    };
+    Some(value.clamp(0.0, 1.0))
+}
+
+// Parse the saturation or lightness of an hsl() color, as a fraction from 0 to 1. The % is
+// optional, as in CSS.
+fn parse_percentage(percentage: &str) -> Option<f64> {
+    let value: f64 = percentage
+        .strip_suffix('%')
+        .unwrap_or(percentage)
+        .parse()
+        .ok()?;
+    Some((value / 100.0).clamp(0.0, 1.0))
+}
+
+// Parse the hue of an hsl() color, in degrees.
+fn parse_hue(hue: &str) -> Option<f64> {
+    let units = [
+        ("deg", 1.0),
+        ("grad", 0.9),
+        ("rad", 180.0 / std::f64::consts::PI),
+        ("turn", 360.0),
+    ];
+    for (unit, degrees) in units {
+        if let Some(value) = hue.strip_suffix(unit) {
+            return Some(value.parse::<f64>().ok()? * degrees);
+        }
+    }
+    hue.parse().ok()
+}
+
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [u8; 3] {
+    let hue = hue.rem_euclid(360.0);
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue {
+        h if h < 60.0 => (chroma, x, 0.0),
+        h if h < 120.0 => (x, chroma, 0.0),
+        h if h < 180.0 => (0.0, chroma, x),
+        h if h < 240.0 => (0.0, x, chroma),
+        h if h < 300.0 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    [r, g, b].map(|channel| ((channel + m) * 255.0).round() as u8)
+}
+
+/// Check that a string is a CSS color, and write it in a canonical form. Colors that
+/// [`Color::parse`] understands become lowercase hex (see [`Color::to_hex`]). Other CSS colors
+/// that can't be turned into RGB, like `var(--accent)` or `oklch(70% 0.1 200)`, are left as-is.
+/// Anything else is an error, with a message describing the problem.
+pub(crate) fn normalize_color(color: &str) -> Result<String, String> {
+    if let Some(parsed) = Color::parse(color) {
+        return Ok(parsed.to_hex());
+    }
+
+    let trimmed = color.trim().to_ascii_lowercase();
+    let is_opaque = OPAQUE_KEYWORDS.contains(&trimmed.as_str())
+        || FUNCTION_RE
+            .captures(&trimmed)
+            .is_some_and(|captures| OPAQUE_FUNCTIONS.contains(&&captures["name"]));
+    if is_opaque {
+        Ok(color.trim().to_string())
+    } else {
+        Err(format!("\"{}\" isn't a CSS color", color))
+    }
+}
+
+/// Check if a field holds a color, and so should be normalized. These are the `color` field and
+/// any field ending in `_color`, like `background_color`.
+pub(crate) fn is_color_field(field: &str) -> bool {
+    field == "color" || field.ends_with("_color")
+}
+
+/// Normalize every color field (see [`is_color_field`]) in a set of fields with
+/// [`normalize_color`]. The `color` field itself must be a CSS color, so that a typo in it is
+/// caught. Other fields ending in `_color` which aren't CSS colors are left as-is, since a config
+/// may use a field like `eye_color: "blue-green"` as free-form text. Returns the name of the
+/// invalid field and a description of the problem on error.
+pub(crate) fn normalize_color_fields(
+    fields: &mut HashMap<String, Value>,
+) -> Result<(), (String, String)> {
+    for (field, value) in fields.iter_mut() {
+        if !is_color_field(field) {
+            continue;
+        }
+        if let Value::String(color) = value {
+            match normalize_color(color) {
+                Ok(normalized) => *color = normalized,
+                Err(message) if field == "color" => return Err((field.clone(), message)),
+                Err(_) => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the fields derived from each color field in a set of fields. For a field called `color`
+/// these are:
+/// - `color_rgb`: the red, green, and blue channels, like `255, 234, 2`, for use in
+///   `rgba({{ user.color_rgb }}, 0.5)`
+/// - `color_contrast`: black or white, whichever is more readable on top of the color
+/// - `color_faded`: the color at half of its opacity
+///
+/// Colors that can't be turned into RGB (see [`normalize_color`]) don't have derived fields.
+pub(crate) fn derived_fields(fields: &HashMap<String, Value>) -> Vec<(String, Value)> {
+    let mut derived = vec![];
+    for (field, value) in fields {
+        if !is_color_field(field) {
+            continue;
+        }
+        let Some(color) = value.as_str().and_then(Color::parse) else {
+            continue;
+        };
+        derived.push((
+            format!("{}{}", field, RGB_SUFFIX),
+            format!("{}, {}, {}", color.r, color.g, color.b).into(),
+        ));
+        derived.push((
+            format!("{}{}", field, CONTRAST_SUFFIX),
+            color.contrast_text().to_hex().into(),
+        ));
+        let faded = Color {
+            a: color.a / 2.0,
+            ..color
+        };
+        derived.push((format!("{}{}", field, FADED_SUFFIX), faded.to_hex().into()));
+    }
+    derived
+}
+
+#[test]
+fn test_colors() {
+    let normalize = |color: &str| normalize_color(color).ok();
+    assert_eq!(normalize("#FFEA02").as_deref(), Some("#ffea02"));
+    assert_eq!(normalize("#fe0").as_deref(), Some("#ffee00"));
+    assert_eq!(normalize("#ffee0080").as_deref(), Some("#ffee0080"));
+    assert_eq!(normalize("RebeccaPurple").as_deref(), Some("#663399"));
+    assert_eq!(normalize("transparent").as_deref(), Some("#00000000"));
+    assert_eq!(normalize("rgb(255, 234, 2)").as_deref(), Some("#ffea02"));
+    assert_eq!(
+        normalize("rgba(255 234 2 / 50%)").as_deref(),
+        Some("#ffea0280")
+    );
+    assert_eq!(normalize("rgb(100%, 0%, 0%)").as_deref(), Some("#ff0000"));
+    assert_eq!(normalize("hsl(120, 100%, 25%)").as_deref(), Some("#008000"));
+    assert_eq!(
+        normalize("hsla(0.5turn 100% 50% / 0.5)").as_deref(),
+        Some("#00ffff80")
+    );
+    assert_eq!(normalize("var(--accent)").as_deref(), Some("var(--accent)"));
+    assert_eq!(
+        normalize("oklch(70% 0.1 200)").as_deref(),
+        Some("oklch(70% 0.1 200)")
+    );
+    assert_eq!(
+        normalize_color("#ffea0").unwrap_err(),
+        "\"#ffea0\" isn't a CSS color"
+    );
+    assert!(normalize("light blue").is_none());
+    assert!(normalize("yelow").is_none());
+    assert!(normalize("rgb(1, 2)").is_none());
+
+    let fields: HashMap<String, Value> = [
+        ("color".to_string(), Value::from("#ffea02")),
+        ("background_color".to_string(), Value::from("#00008b")),
+        ("border_color".to_string(), Value::from("var(--border)")),
+        ("name".to_string(), Value::from("Juice")),
+    ]
+    .into_iter()
+    .collect();
+    let derived: HashMap<String, Value> = derived_fields(&fields).into_iter().collect();
+    assert_eq!(derived.len(), 6);
+    assert_eq!(derived["color_rgb"], "255, 234, 2");
+    assert_eq!(derived["color_contrast"], "#000000");
+    assert_eq!(derived["color_faded"], "#ffea0280");
+    assert_eq!(derived["background_color_contrast"], "#ffffff");
+
+    let dark = Color::from_hex_number(0x36393e);
+    let light = Color::from_hex_number(0xffffff);
+    for key in ["JUICE", "NINE", "TEN", "EGGBUG", "BUGEGG", "A"] {
+        assert_eq!(auto_color(key, dark), auto_color(key, dark));
+        assert!(auto_color(key, dark).contrast(dark) >= AUTO_COLOR_MIN_CONTRAST);
+        assert!(auto_color(key, light).contrast(light) >= AUTO_COLOR_MIN_CONTRAST);
+    }
+    assert_ne!(auto_color("JUICE", dark), auto_color("NINE", dark));
+    let grey = Color::from_hex_number(0x808080);
+    assert_eq!(auto_color("JUICE", grey), grey.contrast_text());
+}
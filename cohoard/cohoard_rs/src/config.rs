@@ -1,33 +1,517 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, str::FromStr};
 
+use lazy_static::lazy_static;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-use crate::User;
+use crate::{
+    at_macros,
+    color::{self, Color},
+    User,
+};
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct UserSchema {
-    key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preset: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extends: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    quirk: Option<Quirk>,
     #[serde(flatten)]
     user: User,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ConfigSchema {
     people: Vec<UserSchema>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    defaults: HashMap<String, Value>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    emoji: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    strict_at_macros: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auto_colors: Option<String>,
+}
+
+lazy_static! {
+    // Matches a line of TOML that couldn't be YAML: a `[table]` or `[[array]]` header, or a
+    // `key = value` pair.
+    static ref TOML_LINE_RE: Regex =
+        Regex::new(r#"^(\[\[?[\w."' -]+\]\]?$|[\w"'.-]+\s*=)"#).unwrap();
+    // The built-in presets, in the order that they're listed in presets.yaml.
+    static ref PRESETS: Vec<Preset> = {
+        let presets: serde_yaml::Mapping =
+            serde_yaml::from_str(include_str!("presets.yaml")).unwrap();
+        presets
+            .into_iter()
+            .map(|(name, user)| Preset {
+                name: name.as_str().unwrap().to_string(),
+                user: serde_yaml::from_value(user).unwrap(),
+            })
+            .collect()
+    };
+}
+
+/// A built-in person, such as eggbug or a Homestuck character, which people in a config can be
+/// based on with `preset`. See [`presets`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Preset {
+    /// The name of the preset, like `cohost-eggbug`.
+    pub name: String,
+    /// The fields of the preset, including its `key`.
+    pub user: User,
+}
+
+impl Preset {
+    /// The key that people based on this preset get if they don't set their own.
+    pub fn key(&self) -> &str {
+        self.user.get_str("key").unwrap_or(&self.name)
+    }
+}
+
+/// Get all of the built-in presets. These are the same presets that the web UI offers.
+pub fn presets() -> &'static [Preset] {
+    &PRESETS
+}
+
+/// Get the built-in preset with the given name, if there is one.
+pub fn get_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+// Like `get_preset`, but an unknown preset is an error.
+fn find_preset(name: &str) -> Result<&'static Preset, Box<dyn Error>> {
+    get_preset(name).ok_or_else(|| format!("There is no preset called \"{}\"", name).into())
+}
+
+/// A file format that configs can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Get the format that files with the given extension (such as "yaml" or "toml") are in.
+    pub fn from_extension(extension: &str) -> Option<ConfigFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            "json" => Some(ConfigFormat::Json),
+            "toml" => Some(ConfigFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format of a config from its contents. This looks at the first line that isn't
+    /// blank or a comment: JSON starts with a `{`, and TOML starts with a `[table]` header or a
+    /// `key = value` pair. Anything else is assumed to be YAML.
+    pub fn detect(config: &str) -> ConfigFormat {
+        let first_line = config
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'));
+        match first_line {
+            Some(line) if line.starts_with('{') => ConfigFormat::Json,
+            Some(line) if TOML_LINE_RE.is_match(line) => ConfigFormat::Toml,
+            _ => ConfigFormat::Yaml,
+        }
+    }
+}
+
+impl FromStr for ConfigFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<ConfigFormat, String> {
+        ConfigFormat::from_extension(format).ok_or_else(|| {
+            format!(
+                "Unknown config format \"{}\" (expected \"yaml\", \"json\", or \"toml\")",
+                format
+            )
+        })
+    }
 }
 
 /// A configuration struct detailing what properties each poster has.
 ///
 /// Each poster has a unique name (usually in all-caps) and can detail things like
 /// their avatar, display name, handle, and other common properies.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Config {
     pub people: HashMap<String, User>,
+    /// Fields that every poster has unless they set them to something else, including posters
+    /// that aren't in the config.
+    #[serde(default)]
+    pub defaults: HashMap<String, Value>,
+    /// Custom emoji, as a map from the emoji's shortcode (without colons) to the image URL.
+    #[serde(default)]
+    pub emoji: HashMap<String, String>,
+    /// If true, at-macros with a key or field that doesn't exist are an error, instead of being
+    /// left in the message as-is.
+    #[serde(default)]
+    pub strict_at_macros: bool,
+    /// If set, posters that aren't in the config are each given their own color, which is
+    /// readable on top of this background color. See [`Config::get_user`].
+    #[serde(default)]
+    pub auto_colors: Option<String>,
+    /// If true, `auto_colors` was turned on by the template rather than by the config. In this
+    /// case, a `color` in the `defaults` isn't replaced by the automatic color. This is set by
+    /// [`crate::render`].
+    #[serde(skip)]
+    pub auto_colors_from_template: bool,
+    /// The typing quirks of each poster, as a map from the poster's key to their quirk.
+    #[serde(default)]
+    pub quirks: HashMap<String, Quirk>,
+    /// Where each field of each poster came from, as a map from the poster's key to a map from
+    /// the field's name to its source. See [`Config::explain`].
+    #[serde(default)]
+    pub sources: HashMap<String, HashMap<String, FieldSource>>,
+}
+
+/// Where the value of a field of a poster came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldSource {
+    /// The name of the config file that the field came from, if it is known. This is set with
+    /// [`Config::set_file`].
+    pub file: Option<String>,
+    pub kind: FieldSourceKind,
+}
+
+/// How a field of a poster was set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FieldSourceKind {
+    /// The field was set on the poster.
+    Person,
+    /// The field was copied from another poster with `extends`.
+    Extends { from: String },
+    /// The field was copied from a built-in preset with `preset`.
+    Preset { name: String },
+    /// The field came from the `defaults` section.
+    Defaults,
+    /// The field was filled in automatically. This is the `key` field, the `name` field if no name
+    /// was given, and the fields derived from colors.
+    Automatic,
 }
 
-/// Load a configuration file from `yaml` text.
+impl FieldSourceKind {
+    // When configs are merged, fields with a higher precedence replace fields with a lower one,
+    // even if they're from an earlier config.
+    fn precedence(&self) -> u8 {
+        match self {
+            FieldSourceKind::Person
+            | FieldSourceKind::Extends { .. }
+            | FieldSourceKind::Preset { .. } => 2,
+            FieldSourceKind::Defaults => 1,
+            FieldSourceKind::Automatic => 0,
+        }
+    }
+}
+
+impl Config {
+    /// Get the poster with the given key. If the poster isn't in the config, this makes a poster
+    /// with the default fields, whose name and key are both `key`.
+    ///
+    /// If `auto_colors` is set, posters that aren't in the config also get a `color` picked from a
+    /// palette with a hash of their key, replacing any default color (unless the automatic colors
+    /// came from the template, in which case the default color wins). Each poster always gets the
+    /// same color, and the color is readable on top of the `auto_colors` background. They also get
+    /// an `avatar_placeholder`, which is a data URL of an image of their initials on their color.
+    /// This is used as their `avatar` too, unless there is a default avatar.
+    pub fn get_user(&self, key: &str) -> User {
+        self.people.get(key).cloned().unwrap_or_else(|| {
+            let mut fields = self.defaults.clone();
+            fields.insert("key".to_string(), key.into());
+            fields.insert("name".to_string(), key.into());
+            let background = self.auto_colors.as_deref().and_then(Color::parse);
+            if let Some(background) = background {
+                let auto_color = color::auto_color(key, background);
+                if !self.auto_colors_from_template || !fields.contains_key("color") {
+                    fields.insert("color".to_string(), auto_color.to_hex().into());
+                }
+                let color = fields
+                    .get("color")
+                    .and_then(Value::as_str)
+                    .and_then(Color::parse)
+                    .unwrap_or(auto_color);
+                let avatar = placeholder_avatar(key, color);
+                fields.insert("avatar_placeholder".to_string(), avatar.clone().into());
+                fields.entry("avatar".to_string()).or_insert(avatar.into());
+            }
+            for (field, value) in color::derived_fields(&fields) {
+                fields.entry(field).or_insert(value);
+            }
+            User { fields }
+        })
+    }
+
+    /// Set the name of the file that this config was loaded from, which is shown by
+    /// [`Config::explain`].
+    pub fn set_file(&mut self, file: &str) {
+        for sources in self.sources.values_mut() {
+            for source in sources.values_mut() {
+                source.file = Some(file.to_string());
+            }
+        }
+    }
+
+    /// Merge another config into this one, as if `other` was loaded after this config. Later
+    /// configs win, so:
+    /// - If a poster is in both configs, each field set in `other` replaces the same field in this
+    ///   config. Fields only in this config are kept. However, fields that `other` only has from
+    ///   its `defaults` (or a `name` that was filled in from the key) don't replace fields that
+    ///   were set on the poster in this config.
+    /// - The `defaults` of `other` are added to the posters in this config which don't already
+    ///   have those fields set, and replace the `defaults` of this config. Likewise, the `defaults`
+    ///   of this config are added to the posters that are only in `other`.
+    /// - Quirks and custom emoji from `other` replace the ones in this config.
+    /// - `strict_at_macros` is set if either config sets it.
+    /// - The `auto_colors` background of `other` replaces the one in this config, if it is set.
+    ///
+    /// Note that `extends` is resolved when each config is loaded, so a poster can't extend a
+    /// poster from a different config.
+    pub fn merge(&mut self, other: Config) {
+        let default_source = |key: &str, field: &str| {
+            other
+                .sources
+                .get(key)
+                .and_then(|sources| sources.get(field))
+                .cloned()
+                .unwrap_or(FieldSource {
+                    file: None,
+                    kind: FieldSourceKind::Defaults,
+                })
+        };
+
+        // The defaults of this config, along with the file each one came from, for the posters that
+        // are only in `other`. This has to be found before `other`'s defaults are added.
+        let defaults: Vec<(String, Value, Option<String>)> = self
+            .defaults
+            .iter()
+            .map(|(field, value)| {
+                let file = self
+                    .sources
+                    .values()
+                    .filter_map(|sources| sources.get(field))
+                    .find(|source| source.kind == FieldSourceKind::Defaults)
+                    .and_then(|source| source.file.clone());
+                (field.clone(), value.clone(), file)
+            })
+            .collect();
+
+        // All of the sources in `other` have the same file, so just use the first one.
+        let other_file = other
+            .sources
+            .values()
+            .flat_map(|sources| sources.values())
+            .find_map(|source| source.file.clone());
+        for (field, value) in &other.defaults {
+            for (key, user) in &mut self.people {
+                let sources = self.sources.entry(key.clone()).or_default();
+                let replace = sources
+                    .get(field)
+                    .is_none_or(|source| source.kind.precedence() <= 1);
+                if replace {
+                    user.fields.insert(field.clone(), value.clone());
+                    sources.insert(
+                        field.clone(),
+                        FieldSource {
+                            file: other_file.clone(),
+                            kind: FieldSourceKind::Defaults,
+                        },
+                    );
+                }
+            }
+        }
+
+        for (key, mut other_user) in other.people {
+            let Some(user) = self.people.get_mut(&key) else {
+                let mut sources = other.sources.get(&key).cloned().unwrap_or_default();
+                for (field, value, file) in &defaults {
+                    if !other_user.fields.contains_key(field) {
+                        other_user.fields.insert(field.clone(), value.clone());
+                        sources.insert(
+                            field.clone(),
+                            FieldSource {
+                                file: file.clone(),
+                                kind: FieldSourceKind::Defaults,
+                            },
+                        );
+                    }
+                }
+                self.sources.insert(key.clone(), sources);
+                self.people.insert(key, other_user);
+                continue;
+            };
+
+            let sources = self.sources.entry(key.clone()).or_default();
+            for (field, value) in other_user.fields {
+                let other_source = default_source(&key, &field);
+                let replace = sources.get(&field).is_none_or(|source| {
+                    source.kind.precedence() <= other_source.kind.precedence()
+                });
+                if replace {
+                    user.fields.insert(field.clone(), value);
+                    sources.insert(field, other_source);
+                }
+            }
+        }
+
+        // The colors may have changed, so the fields derived from them need to be updated too.
+        for (key, user) in &mut self.people {
+            let sources = self.sources.entry(key.clone()).or_default();
+            for (field, value) in color::derived_fields(&user.fields) {
+                let replace = sources
+                    .get(&field)
+                    .is_none_or(|source| source.kind == FieldSourceKind::Automatic);
+                if replace {
+                    user.fields.insert(field.clone(), value);
+                    let file = sources.get(&field).and_then(|source| source.file.clone());
+                    sources.insert(
+                        field,
+                        FieldSource {
+                            file,
+                            kind: FieldSourceKind::Automatic,
+                        },
+                    );
+                }
+            }
+        }
+
+        self.defaults.extend(other.defaults);
+        self.emoji.extend(other.emoji);
+        self.quirks.extend(other.quirks);
+        self.strict_at_macros |= other.strict_at_macros;
+        if other.auto_colors.is_some() {
+            self.auto_colors = other.auto_colors;
+            self.auto_colors_from_template = other.auto_colors_from_template;
+        }
+    }
+
+    /// Describe where every field of every poster came from, one field per line. For example:
+    /// ```no_compile
+    /// JUICE.color = "#ffea02" (from cast.yaml)
+    /// JUICE.name = "Juice" (from cast.yaml, extends JUICE1)
+    /// JUICE.radius = "50%" (from story.yaml, defaults)
+    /// ```
+    pub fn explain(&self) -> String {
+        let mut keys: Vec<&String> = self.people.keys().collect();
+        keys.sort();
+
+        let mut lines = vec![];
+        for key in keys {
+            let user = &self.people[key];
+            let mut fields: Vec<(&String, &Value)> = user.fields.iter().collect();
+            fields.sort_by_key(|(field, _)| *field);
+            for (field, value) in fields {
+                let source = self.sources.get(key).and_then(|sources| sources.get(field));
+                let mut notes = vec![];
+                if let Some(file) = source.and_then(|source| source.file.as_ref()) {
+                    notes.push(format!("from {}", file));
+                }
+                match source.map(|source| &source.kind) {
+                    Some(FieldSourceKind::Extends { from }) => {
+                        notes.push(format!("extends {}", from))
+                    }
+                    Some(FieldSourceKind::Preset { name }) => {
+                        notes.push(format!("preset {}", name))
+                    }
+                    Some(FieldSourceKind::Defaults) => notes.push("defaults".to_string()),
+                    Some(FieldSourceKind::Automatic) => notes.push("automatic".to_string()),
+                    Some(FieldSourceKind::Person) => (),
+                    None => notes.push("unknown source".to_string()),
+                }
+
+                let mut line = format!("{}.{} = {}", key, field, value);
+                if !notes.is_empty() {
+                    line.push_str(&format!(" ({})", notes.join(", ")));
+                }
+                lines.push(line);
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+/// A typing quirk, which transforms the text of every message sent by a poster, like the typing
+/// styles of Homestuck characters.
 ///
-/// The yaml scheme is similar to this:
+/// The transforms are applied in order: first the `case`, then each of the `replace` rules, and
+/// then the `prefix` and `suffix` are added.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quirk {
+    #[serde(default)]
+    pub case: QuirkCase,
+    /// Regex replacements, applied in order. The replacement text can refer to capture groups
+    /// with `$1`, `$name`, etc.
+    #[serde(default)]
+    pub replace: Vec<QuirkReplacement>,
+    /// Text added to the start of every message.
+    #[serde(default)]
+    pub prefix: String,
+    /// Text added to the end of every message.
+    #[serde(default)]
+    pub suffix: String,
+}
+
+/// A case transform for a [`Quirk`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QuirkCase {
+    /// Leave the case of the message alone.
+    #[default]
+    None,
+    /// MAKE EVERYTHING UPPERCASE.
+    Upper,
+    /// make everything lowercase.
+    Lower,
+    /// AlTeRnAtE bEtWeEn UpPeRcAsE aNd LoWeRcAsE lEtTeRs.
+    Alternating,
+}
+
+/// A regex replacement for a [`Quirk`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuirkReplacement {
+    /// The regex to replace. This is compiled when the config is loaded, so an invalid regex is an
+    /// error then, instead of when rendering.
+    #[serde(
+        serialize_with = "serialize_regex",
+        deserialize_with = "deserialize_regex"
+    )]
+    pub from: Regex,
+    /// The text to replace it with.
+    pub to: String,
+}
+
+impl PartialEq for QuirkReplacement {
+    fn eq(&self, other: &QuirkReplacement) -> bool {
+        self.from.as_str() == other.from.as_str() && self.to == other.to
+    }
+}
+
+impl Eq for QuirkReplacement {}
+
+// Write a regex as its pattern.
+fn serialize_regex<S: serde::Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+// Read and compile a regex from its pattern.
+fn deserialize_regex<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern)
+        .map_err(|err| serde::de::Error::custom(format!("Invalid quirk regex: {}", err)))
+}
+
+/// Load a configuration file, guessing whether it is YAML, JSON, or TOML with
+/// [`ConfigFormat::detect`]. Use [`load_config_as`] if the format is already known.
+///
+/// The yaml scheme is similar to this (JSON and TOML configs have the same structure):
 /// ```yaml
 /// people:
 /// - key: KARKAT
@@ -39,29 +523,697 @@ pub struct Config {
 ///   name: Juipter Icey Moon Explorer
 ///   color: "#ffea02"
 ///   avatar: your_url_to_the_avatar_image
+///   verified: true
+///   badges: [astronaut, probe]
+/// - key: JUICE2
+///   extends: JUICE
+///   name: Juice (20020)
+/// - preset: cohost-eggbug
+/// - key: BUGEGG
+///   preset: cohost-bugegg
+///   name: bug egg (evil)
+/// - key: TEREZI
+///   quirk:
+///     case: upper
+///     replace:
+///     - from: A
+///       to: "4"
+///     - from: I
+///       to: "1"
+/// emoji:
+///   eggbug: your_url_to_the_emoji_image
+/// strict_at_macros: true
+/// auto_colors: "#36393e"
+/// defaults:
+///   color: "#ffffff"
 /// ```
-/// Note that only `key` is required. The key is usually in ALL CAPS but this is not required.
-/// The key should match each person that speaks in the input chat log. Other properties for each
-/// person can be custom to the specific template. For example, in the Discord template, `handle` is
-/// not required and can be left off, while in the Twitter template, `handle` (if provided) sets the
-/// handle that displays on the tweet.
+/// Note that only `key` is required (unless there is a `preset`). The key is usually in ALL CAPS
+/// but this is not required. The key should match each person that speaks in the input chat log.
+/// Other properties for each person can be custom to the specific template. For example, in the
+/// Discord template, `handle` is not required and can be left off, while in the Twitter template,
+/// `handle` (if provided) sets the handle that displays on the tweet.
+///
+/// Fields can be any YAML value, not just strings. For example, `verified: true` is a boolean and
+/// `badges: [astronaut, probe]` is a list, which templates can check with `{% if user.verified %}`
+/// or loop over with `{% for badge in user.badges %}`.
+///
+/// `preset` is optional, and copies all of the fields of one of the built-in [`presets`], such as
+/// `cohost-eggbug` or `homestuck-karkat`, which can then be overriden. If the person doesn't have a
+/// `key`, they get the preset's key. An unknown preset is an error.
+///
+/// `extends` is optional, and copies all of the fields (and the quirk) of another person, which
+/// can then be overriden. The `defaults` section is also optional, and sets fields for every
+/// person, including people who aren't in the config. Fields are merged in this order, with later
+/// fields overriding earlier ones:
+/// 1. The `defaults`
+/// 2. The fields of the person being extended, if any (which are merged in the same order)
+/// 3. The fields of the preset, if any
+/// 4. The fields of the person themselves
+///
+/// If no `name` is provided by any of these, the name defaults to the key.
+///
+/// The `color` field must be a CSS color, and an invalid color is an error. Fields ending in
+/// `_color` (like `background_color`) are treated as CSS colors too. Hex colors, named colors, and
+/// `rgb()` and `hsl()` colors are written as lowercase hex, so `yellow` becomes `#ffff00`, and each
+/// of these colors also gets some derived fields for templates to use. For `color`, these are:
+/// - `color_rgb`: the red, green, and blue channels, like `255, 234, 2`, which can be used as
+///   `rgba({{ user.color_rgb }}, 0.5)`
+/// - `color_contrast`: black or white, whichever is more readable on top of the color
+/// - `color_faded`: the color at half of its opacity
+///
+/// Other CSS colors, like `var(--accent)` or `oklch(70% 0.1 200)`, are left as-is and don't get
+/// derived fields. So are values of `_color` fields that aren't colors at all, like
+/// `eye_color: blue-green`, so that these fields can still be used for other things. Templates
+/// which need one of these fields to be a color can declare it in their person schema, and
+/// [`crate::template::validate_config`] will catch any invalid colors.
+///
+/// `quirk` is optional, and sets a typing quirk which transforms every message sent by that
+/// person. See [`Quirk`] for the available transforms. The regexes are checked when the config is
+/// loaded, and an invalid regex is an error.
+///
+/// The `emoji` section is optional, and maps shortcodes to custom emoji images. In the example
+/// above, `:eggbug:` in a message would be replaced with the image.
+///
+/// `strict_at_macros` is optional and defaults to false. If true, an at-macro like `@KEY.field`
+/// whose key or field doesn't exist is an error, which is useful for catching typos.
+///
+/// `auto_colors` is optional, and gives each speaker who isn't in the config their own color and
+/// a placeholder avatar, which is handy for quick drafts. The value is the background color that
+/// the speakers' colors need to be readable on. See [`Config::get_user`]. Templates can also turn
+/// this on (see [`crate::render`]), but the config takes priority.
 pub fn load_config(config: &str) -> Result<Config, Box<dyn Error>> {
-    let config: ConfigSchema = serde_yaml::from_str(config)?;
+    load_config_as(config, ConfigFormat::detect(config))
+}
+
+/// Load a configuration file written in the given format. See [`load_config`] for the scheme.
+pub fn load_config_as(config: &str, format: ConfigFormat) -> Result<Config, Box<dyn Error>> {
+    let mut config: ConfigSchema = match format {
+        ConfigFormat::Yaml => serde_yaml::from_str(config)?,
+        ConfigFormat::Json => serde_json::from_str(config)?,
+        ConfigFormat::Toml => toml::from_str(config)?,
+    };
+    color::normalize_color_fields(&mut config.defaults)
+        .map_err(|(field, message)| format!("Invalid color for defaults.{}: {}", field, message))?;
+    if let Some(background) = &mut config.auto_colors {
+        let Some(color) = Color::parse(background) else {
+            return Err(format!(
+                "Invalid color for auto_colors: \"{}\" isn't a hex, named, rgb(), or hsl() color",
+                background
+            )
+            .into());
+        };
+        *background = color.to_hex();
+    }
+    let mut schemas: HashMap<String, &UserSchema> = HashMap::new();
+    for person in &config.people {
+        let key = match (&person.key, &person.preset) {
+            (Some(key), _) => key.clone(),
+            (None, Some(preset)) => find_preset(preset)?.key().to_string(),
+            (None, None) => return Err("Every person in the config needs a key or a preset".into()),
+        };
+        schemas.insert(key, person);
+    }
     let mut people = HashMap::new();
+    let mut quirks = HashMap::new();
+    let mut sources = HashMap::new();
+
+    for key in schemas.keys() {
+        let person = resolve_extends(&schemas, key, &mut vec![])?;
+        if let Some(quirk) = person.quirk {
+            quirks.insert(key.clone(), quirk);
+        }
 
-    for mut person in config.people {
+        let mut fields = config.defaults.clone();
+        let mut field_sources: HashMap<String, FieldSourceKind> = fields
+            .keys()
+            .map(|field| (field.clone(), FieldSourceKind::Defaults))
+            .collect();
+        fields.extend(person.fields);
+        field_sources.extend(person.sources);
+        color::normalize_color_fields(&mut fields).map_err(|(field, message)| {
+            format!("Invalid color for {}.{}: {}", key, field, message)
+        })?;
         // Ensure that the User always has access to its own key.
-        person
-            .user
-            .fields
-            .insert("key".to_string(), person.key.clone());
+        fields.insert("key".to_string(), key.as_str().into());
+        field_sources.insert("key".to_string(), FieldSourceKind::Automatic);
         // Also ensure that a User always has a name. If no name is provided, default to the key.
-        let _ = person
-            .user
-            .fields
-            .try_insert("name".to_string(), person.key.clone());
-        people.insert(person.key, person.user);
+        if fields
+            .try_insert("name".to_string(), key.as_str().into())
+            .is_ok()
+        {
+            field_sources.insert("name".to_string(), FieldSourceKind::Automatic);
+        }
+        for (field, value) in color::derived_fields(&fields) {
+            if fields.try_insert(field.clone(), value).is_ok() {
+                field_sources.insert(field, FieldSourceKind::Automatic);
+            }
+        }
+        people.insert(key.clone(), User { fields });
+
+        let field_sources = field_sources
+            .into_iter()
+            .map(|(field, kind)| (field, FieldSource { file: None, kind }))
+            .collect();
+        sources.insert(key.clone(), field_sources);
+    }
+
+    Ok(Config {
+        people,
+        defaults: config.defaults,
+        emoji: config.emoji,
+        strict_at_macros: config.strict_at_macros,
+        auto_colors: config.auto_colors,
+        auto_colors_from_template: false,
+        quirks,
+        sources,
+    })
+}
+
+/// Write a config in the given format, such that loading it again with [`load_config_as`] gives
+/// back the same config.
+///
+/// Since the config only stores the final fields of each person, people don't `extend` each other
+/// or use presets in the output. Instead, each person has all of their fields written out.
+pub fn save_config(config: &Config, format: ConfigFormat) -> Result<String, Box<dyn Error>> {
+    let mut people: Vec<UserSchema> = config
+        .people
+        .iter()
+        .map(|(key, user)| {
+            let mut user = user.clone();
+            user.fields.remove("key");
+            // Fields derived from colors are filled in again when the config is loaded.
+            for (field, _) in color::derived_fields(&user.fields) {
+                let source = config
+                    .sources
+                    .get(key)
+                    .and_then(|sources| sources.get(&field));
+                if source.is_none_or(|source| source.kind == FieldSourceKind::Automatic) {
+                    user.fields.remove(&field);
+                }
+            }
+            UserSchema {
+                key: Some(key.clone()),
+                preset: None,
+                extends: None,
+                quirk: config.quirks.get(key).cloned(),
+                user,
+            }
+        })
+        .collect();
+    people.sort_by(|a, b| a.key.cmp(&b.key));
+    let schema = ConfigSchema {
+        people,
+        defaults: config.defaults.clone(),
+        emoji: config.emoji.clone(),
+        strict_at_macros: config.strict_at_macros,
+        auto_colors: config.auto_colors.clone(),
+    };
+
+    // Going through a serde_json::Value sorts all of the maps, so that the output is the same
+    // every time.
+    let schema = serde_json::to_value(schema)?;
+    let output = match format {
+        ConfigFormat::Yaml => serde_yaml::to_string(&schema)?,
+        ConfigFormat::Json => serde_json::to_string_pretty(&schema)?,
+        // The TOML serializer needs tables to come after all of the other values in a table, which
+        // toml::Value takes care of.
+        ConfigFormat::Toml => toml::to_string(&toml::Value::try_from(schema)?)?,
+    };
+    Ok(output)
+}
+
+// Make a data URL of an image of a poster's initials on top of their color, for posters who don't
+// have an avatar.
+fn placeholder_avatar(key: &str, color: Color) -> String {
+    let initials: String = at_macros::initials(key).chars().take(2).collect();
+    let svg = format!(
+        "<svg xmlns='http://www.w3.org/2000/svg' viewBox='0 0 64 64'>\
+         <rect width='64' height='64' fill='{}'/>\
+         <text x='32' y='32' dy='0.35em' text-anchor='middle' font-family='sans-serif' \
+         font-size='28' fill='{}'>{}</text></svg>",
+        color.to_hex(),
+        color.contrast_text().to_hex(),
+        tera::escape_html(&initials)
+    );
+
+    let mut url = "data:image/svg+xml,".to_string();
+    for byte in svg.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~!$&'()*+,;=:@/?".contains(&byte) {
+            url.push(byte as char);
+        } else {
+            url.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    url
+}
+
+// The fields and quirk of a person, along with how each field was set.
+#[derive(Default)]
+struct ResolvedPerson {
+    fields: HashMap<String, Value>,
+    sources: HashMap<String, FieldSourceKind>,
+    quirk: Option<Quirk>,
+}
+
+// Get the fields and quirk of a person, including the ones they get from the person they extend.
+// `chain` is the list of people that are extending this person, and is used to detect cycles.
+fn resolve_extends(
+    schemas: &HashMap<String, &UserSchema>,
+    key: &str,
+    chain: &mut Vec<String>,
+) -> Result<ResolvedPerson, Box<dyn Error>> {
+    let Some(person) = schemas.get(key) else {
+        return Err(format!(
+            "{} extends {}, but there is no person with that key",
+            chain.last().map_or("", String::as_str),
+            key
+        )
+        .into());
+    };
+    if chain.iter().any(|other| other == key) {
+        chain.push(key.to_string());
+        return Err(format!(
+            "People can't extend themselves: {}",
+            chain.join(" extends ")
+        )
+        .into());
+    }
+    chain.push(key.to_string());
+
+    let mut resolved = match &person.extends {
+        Some(parent) => {
+            let mut resolved = resolve_extends(schemas, parent, chain)?;
+            for kind in resolved.sources.values_mut() {
+                if *kind == FieldSourceKind::Person {
+                    *kind = FieldSourceKind::Extends {
+                        from: parent.clone(),
+                    };
+                }
+            }
+            resolved
+        }
+        None => ResolvedPerson::default(),
+    };
+    if let Some(name) = &person.preset {
+        let preset = find_preset(name)?;
+        for (field, value) in &preset.user.fields {
+            // The key comes from the person, not the preset.
+            if field == "key" {
+                continue;
+            }
+            resolved.fields.insert(field.clone(), value.clone());
+            resolved.sources.insert(
+                field.clone(),
+                FieldSourceKind::Preset { name: name.clone() },
+            );
+        }
+    }
+    for (field, value) in &person.user.fields {
+        resolved.fields.insert(field.clone(), value.clone());
+        resolved
+            .sources
+            .insert(field.clone(), FieldSourceKind::Person);
+    }
+    if person.quirk.is_some() {
+        resolved.quirk = person.quirk.clone();
+    }
+    Ok(resolved)
+}
+
+#[test]
+fn test_defaults_and_extends() {
+    let config = r##"people:
+    - key: JUICE
+      name: Juice
+      color: "#ffea02"
+      quirk:
+        case: upper
+    - key: JUICE2
+      extends: JUICE
+      avatar: juice2.png
+      verified: true
+      stats:
+        followers: 12
+    - key: JUICE3
+      extends: JUICE2
+      color: "#000000"
+defaults:
+  color: "#ffffff"
+  border_radius: 50%
+  "##;
+    let config = load_config(config).unwrap();
+    let field = |key: &str, field: &str| config.get_user(key).get_str(field).map(str::to_string);
+
+    assert_eq!(field("JUICE3", "name").as_deref(), Some("Juice"));
+    assert_eq!(field("JUICE3", "key").as_deref(), Some("JUICE3"));
+    assert_eq!(field("JUICE3", "avatar").as_deref(), Some("juice2.png"));
+    assert_eq!(field("JUICE3", "color").as_deref(), Some("#000000"));
+    assert_eq!(field("JUICE3", "border_radius").as_deref(), Some("50%"));
+    assert_eq!(field("JUICE2", "color").as_deref(), Some("#ffea02"));
+    let juice3 = config.get_user("JUICE3");
+    assert_eq!(juice3.fields["verified"], Value::Bool(true));
+    assert_eq!(juice3.fields["stats"]["followers"], Value::from(12));
+    assert_eq!(
+        config.quirks.get("JUICE3").map(|quirk| quirk.case),
+        Some(QuirkCase::Upper)
+    );
+    // People who aren't in the config still get the defaults.
+    assert_eq!(field("NINE", "name").as_deref(), Some("NINE"));
+    assert_eq!(field("NINE", "color").as_deref(), Some("#ffffff"));
+
+    let cycle = "people:\n  - key: A\n    extends: B\n  - key: B\n    extends: A\n";
+    assert!(load_config(cycle).is_err());
+    let missing = "people:\n  - key: A\n    extends: B\n";
+    assert_eq!(
+        load_config(missing).unwrap_err().to_string(),
+        "A extends B, but there is no person with that key"
+    );
+}
+
+#[test]
+fn test_config_formats() {
+    let yaml = r##"people:
+    - key: JUICE
+      name: Juice
+      color: "#ffea02"
+      stats:
+        followers: 12
+      quirk:
+        case: upper
+emoji:
+  eggbug: eggbug.png
+  "##;
+    let json = r##"{
+  "people": [
+    {"key": "JUICE", "name": "Juice", "color": "#ffea02", "stats": {"followers": 12}, "quirk": {"case": "upper"}}
+  ],
+  "emoji": {"eggbug": "eggbug.png"}
+}"##;
+    let toml = r##"
+[[people]]
+key = "JUICE"
+name = "Juice"
+color = "#ffea02"
+stats = { followers = 12 }
+quirk = { case = "upper" }
+
+[emoji]
+eggbug = "eggbug.png"
+"##;
+    assert_eq!(ConfigFormat::detect(yaml), ConfigFormat::Yaml);
+    assert_eq!(ConfigFormat::detect(json), ConfigFormat::Json);
+    assert_eq!(ConfigFormat::detect(toml), ConfigFormat::Toml);
+
+    let expected = save_config(&load_config(yaml).unwrap(), ConfigFormat::Json).unwrap();
+    assert_eq!(
+        save_config(&load_config(json).unwrap(), ConfigFormat::Json).unwrap(),
+        expected
+    );
+    assert_eq!(
+        save_config(&load_config(toml).unwrap(), ConfigFormat::Json).unwrap(),
+        expected
+    );
+
+    // Saving and loading a config in any format gives back the same config.
+    for format in [ConfigFormat::Yaml, ConfigFormat::Json, ConfigFormat::Toml] {
+        let saved = save_config(&load_config(yaml).unwrap(), format).unwrap();
+        let loaded = load_config_as(&saved, format).unwrap();
+        assert_eq!(save_config(&loaded, ConfigFormat::Json).unwrap(), expected);
+    }
+
+    // Quirk regexes are compiled when the config is loaded.
+    let err = load_config("people:\n  - key: JUICE\n    quirk:\n      replace:\n        - from: \"(\"\n          to: x\n")
+        .unwrap_err();
+    assert!(err.to_string().contains("Invalid quirk regex"));
+}
+
+#[test]
+fn test_merge() {
+    let cast = r##"people:
+    - key: JUICE
+      name: Juice
+      color: "#ffea02"
+    - key: NINE
+      name: Nine
+defaults:
+  radius: 50%
+  border: 2px
+  "##;
+    let story = r##"people:
+    - key: JUICE
+      color: "#000000"
+    - key: TEN
+defaults:
+  radius: 0%
+  avatar: blank.png
+  "##;
+    let mut config = load_config(cast).unwrap();
+    config.set_file("cast.yaml");
+    let mut story = load_config(story).unwrap();
+    story.set_file("story.yaml");
+    config.merge(story);
+
+    // Posters who are only in the story still get the defaults of the cast.
+    assert_eq!(
+        config.explain(),
+        r##"JUICE.avatar = "blank.png" (from story.yaml, defaults)
+JUICE.border = "2px" (from cast.yaml, defaults)
+JUICE.color = "#000000" (from story.yaml)
+JUICE.color_contrast = "#ffffff" (from story.yaml, automatic)
+JUICE.color_faded = "#00000080" (from story.yaml, automatic)
+JUICE.color_rgb = "0, 0, 0" (from story.yaml, automatic)
+JUICE.key = "JUICE" (from story.yaml, automatic)
+JUICE.name = "Juice" (from cast.yaml)
+JUICE.radius = "0%" (from story.yaml, defaults)
+NINE.avatar = "blank.png" (from story.yaml, defaults)
+NINE.border = "2px" (from cast.yaml, defaults)
+NINE.key = "NINE" (from cast.yaml, automatic)
+NINE.name = "Nine" (from cast.yaml)
+NINE.radius = "0%" (from story.yaml, defaults)
+TEN.avatar = "blank.png" (from story.yaml, defaults)
+TEN.border = "2px" (from cast.yaml, defaults)
+TEN.key = "TEN" (from story.yaml, automatic)
+TEN.name = "TEN" (from story.yaml, automatic)
+TEN.radius = "0%" (from story.yaml, defaults)"##
+    );
+    assert_eq!(config.get_user("TEN").get_str("border"), Some("2px"));
+    assert_eq!(config.get_user("GUEST").get_str("border"), Some("2px"));
+}
+
+#[test]
+fn test_presets() {
+    assert!(presets().len() > 50);
+    for preset in presets() {
+        assert!(
+            preset.user.get_str("key").is_some(),
+            "{} has no key",
+            preset.name
+        );
     }
+    assert_eq!(
+        get_preset("homestuck-)(").map(Preset::key),
+        Some("CONDESCE")
+    );
+
+    let config = r##"people:
+    - preset: cohost-eggbug
+    - key: BUGEGG
+      preset: cohost-bugegg
+      name: bug egg (evil)
+    - key: EGGBUG2
+      extends: EGGBUG
+      preset: cohost-bugegg
+  "##;
+    let mut config = load_config(config).unwrap();
+    let field = |key: &str, field: &str| config.get_user(key).get_str(field).map(str::to_string);
+
+    assert_eq!(field("EGGBUG", "name").as_deref(), Some("egg bug!"));
+    assert_eq!(field("EGGBUG", "handle").as_deref(), Some("eggbug"));
+    assert_eq!(field("BUGEGG", "name").as_deref(), Some("bug egg (evil)"));
+    assert_eq!(field("BUGEGG", "color").as_deref(), Some("#258359"));
+    assert_eq!(field("EGGBUG2", "key").as_deref(), Some("EGGBUG2"));
+    assert_eq!(field("EGGBUG2", "handle").as_deref(), Some("bugegg"));
+
+    config.set_file("cast.yaml");
+    let explanation = config.explain();
+    assert!(
+        explanation.contains("BUGEGG.color = \"#258359\" (from cast.yaml, preset cohost-bugegg)")
+    );
+    assert!(explanation.contains("BUGEGG.name = \"bug egg (evil)\" (from cast.yaml)"));
+
+    let err = load_config("people:\n- preset: cohost-eggbugg\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "There is no preset called \"cohost-eggbugg\""
+    );
+    assert!(load_config("people:\n- name: Nobody\n").is_err());
+}
+
+// The web UI falls back to presets.js when it's using a version of cohoard without the preset
+// registry, so presets.js is generated from presets.yaml. This fails if presets.js is out of date,
+// and writes a new one if `UPDATE_PRESETS_JS` is set.
+#[test]
+fn test_presets_js() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../../presets.js");
+    // presets.js isn't part of the published crate.
+    let Ok(old) = std::fs::read_to_string(path) else {
+        return;
+    };
+
+    let mut js = String::from(
+        "// This file is generated from cohoard/cohoard_rs/src/presets.yaml, so don't edit it by hand.\n\
+         // Run `UPDATE_PRESETS_JS=1 cargo test` in cohoard/cohoard_rs to update it. It's only used with\n\
+         // versions of cohoard that don't have the preset registry.\n\
+         /** @type {{[key: string]: object}} */\n\
+         export const PRESETS = {\n",
+    );
+    let yaml = include_str!("presets.yaml");
+    // The section comments (like `# HOMESTUCK - BETA KIDS`) of presets.yaml, as a map from the
+    // first preset in each section to its comment. The comments before the first blank line are
+    // about the file itself.
+    let mut comments = HashMap::new();
+    let mut comment = None;
+    for line in yaml.lines().skip_while(|line| !line.is_empty()) {
+        if let Some(text) = line.strip_prefix("# ") {
+            comment = Some(text);
+        } else if let Some(name) = line
+            .strip_prefix('"')
+            .and_then(|line| line.split('"').next())
+        {
+            if let Some(comment) = comment.take() {
+                comments.insert(name, comment);
+            }
+        }
+    }
+
+    let presets: serde_yaml::Mapping = serde_yaml::from_str(yaml).unwrap();
+    for (name, fields) in &presets {
+        if let Some(comment) = comments.get(name.as_str().unwrap()) {
+            js.push_str(&format!("    // {}\n", comment));
+        }
+        js.push_str(&format!(
+            "    {}: {{\n",
+            serde_json::to_string(name).unwrap()
+        ));
+        for (field, value) in fields.as_mapping().unwrap() {
+            js.push_str(&format!(
+                "        {}: {},\n",
+                field.as_str().unwrap(),
+                serde_json::to_string(value).unwrap()
+            ));
+        }
+        js.push_str("    },\n");
+    }
+    js.push_str("};\n");
+
+    if std::env::var_os("UPDATE_PRESETS_JS").is_some() {
+        std::fs::write(path, js).unwrap();
+    } else {
+        assert!(
+            old == js,
+            "presets.js is out of date, run `UPDATE_PRESETS_JS=1 cargo test` to update it"
+        );
+    }
+}
+
+#[test]
+fn test_colors() {
+    let config = r##"people:
+    - key: JUICE
+      color: Yellow
+      background_color: rgb(0, 0, 139)
+    - key: NINE
+      color: var(--nine)
+      color_rgb: "1, 2, 3"
+defaults:
+  color: "#FFF"
+  "##;
+    let config = load_config(config).unwrap();
+    let field = |key: &str, field: &str| config.get_user(key).get_str(field).map(str::to_string);
+
+    assert_eq!(field("JUICE", "color").as_deref(), Some("#ffff00"));
+    assert_eq!(field("JUICE", "color_rgb").as_deref(), Some("255, 255, 0"));
+    assert_eq!(
+        field("JUICE", "background_color").as_deref(),
+        Some("#00008b")
+    );
+    assert_eq!(
+        field("JUICE", "background_color_contrast").as_deref(),
+        Some("#ffffff")
+    );
+    assert_eq!(field("NINE", "color").as_deref(), Some("var(--nine)"));
+    assert_eq!(field("NINE", "color_rgb").as_deref(), Some("1, 2, 3"));
+    assert_eq!(field("NINE", "color_faded"), None);
+    assert_eq!(field("TEN", "color").as_deref(), Some("#ffffff"));
+    assert_eq!(field("TEN", "color_faded").as_deref(), Some("#ffffff80"));
+
+    // Derived fields follow the color when a later config changes it, and aren't saved.
+    let mut merged = config.clone();
+    merged.merge(load_config("people:\n- key: JUICE\n  color: red\n").unwrap());
+    assert_eq!(
+        merged.get_user("JUICE").get_str("color_rgb"),
+        Some("255, 0, 0")
+    );
+    let saved = save_config(&merged, ConfigFormat::Yaml).unwrap();
+    assert!(!saved.contains("color_contrast") && saved.contains("color_rgb: \"1, 2, 3\""));
+
+    let err = load_config("people:\n- key: JUICE\n  color: \"#ffea0\"\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invalid color for JUICE.color: \"#ffea0\" isn't a CSS color"
+    );
+    let err = load_config("people: []\ndefaults:\n  color: yelow\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Invalid color for defaults.color: \"yelow\" isn't a CSS color"
+    );
+
+    // Other color fields that aren't colors are left alone, and don't get derived fields.
+    let config = load_config(
+        "people:\n- key: JUICE\n  eye_color: blue-green\ndefaults:\n  border_color: yelow\n",
+    )
+    .unwrap();
+    let juice = config.get_user("JUICE");
+    assert_eq!(juice.get_str("eye_color"), Some("blue-green"));
+    assert_eq!(juice.get_str("eye_color_rgb"), None);
+    assert_eq!(juice.get_str("border_color"), Some("yelow"));
+}
+
+#[test]
+fn test_auto_colors() {
+    let config = load_config("people:\n- key: JUICE\nauto_colors: rgb(54, 57, 62)\n").unwrap();
+    assert_eq!(config.auto_colors.as_deref(), Some("#36393e"));
+    let background = Color::parse("#36393e").unwrap();
+
+    let nine = config.get_user("NINE");
+    let color = Color::parse(nine.get_str("color").unwrap()).unwrap();
+    assert_eq!(color, color::auto_color("NINE", background));
+    assert_eq!(
+        nine.get_str("color_rgb"),
+        Some(format!("{}, {}, {}", color.r, color.g, color.b).as_str())
+    );
+    let avatar = nine.get_str("avatar").unwrap();
+    assert!(avatar.starts_with("data:image/svg+xml,%3Csvg"));
+    assert!(avatar.contains("%3EN%3C/text%3E") && !avatar.contains(char::is_whitespace));
+    assert_eq!(nine.get_str("avatar_placeholder"), Some(avatar));
+    assert_eq!(config.get_user("JUICE").get_str("color"), None);
+
+    let config =
+        load_config("people: []\nauto_colors: white\ndefaults:\n  avatar: blank.png\n").unwrap();
+    assert_eq!(config.get_user("NINE").get_str("avatar"), Some("blank.png"));
+    assert!(load_config("people: []\n")
+        .unwrap()
+        .get_user("NINE")
+        .get_str("color")
+        .is_none());
+    assert!(load_config("people: []\nauto_colors: var(--background)\n").is_err());
 
-    Ok(Config { people })
+    // A default color replaces automatic colors from the template, but not from the config.
+    let mut config =
+        load_config("people: []\nauto_colors: white\ndefaults:\n  color: red\n").unwrap();
+    assert_ne!(config.get_user("NINE").get_str("color"), Some("#ff0000"));
+    config.auto_colors_from_template = true;
+    let nine = config.get_user("NINE");
+    assert_eq!(nine.get_str("color"), Some("#ff0000"));
+    assert!(nine.get_str("avatar").unwrap().contains("%23ff0000"));
 }
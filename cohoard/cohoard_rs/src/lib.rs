@@ -4,27 +4,63 @@ use std::{collections::HashMap, error::Error};
 
 use css_inline::{CSSInliner, InlineError};
 use kuchiki::{traits::TendrilSink, NodeRef};
-use lazy_static::lazy_static;
-use pulldown_cmark::html;
-use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
+pub mod ast;
+mod at_macros;
+mod color;
 pub mod config;
+mod markdown;
+mod quirks;
+pub mod template;
+use at_macros::AtMacroWrapper;
 use config::Config;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
+    /// The fields of the user, as set in the config. Fields can be any YAML value, such as
+    /// strings, numbers, booleans, lists, or maps.
     #[serde(flatten)]
-    fields: HashMap<String, String>,
+    fields: HashMap<String, serde_json::Value>,
+}
+
+impl User {
+    /// Get the value of a field, if it is a string.
+    pub fn get_str(&self, field: &str) -> Option<&str> {
+        self.fields.get(field).and_then(serde_json::Value::as_str)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 #[serde(rename_all = "lowercase")]
 pub enum ChatlogElement {
-    Timestamp { message: String },
-    Post { user: User, message: String },
+    Timestamp {
+        message: String,
+    },
+    Post {
+        user: User,
+        message: String,
+        /// The users mentioned in the message with `@KEY` or `<@KEY>`, in order of first mention.
+        mentions: Vec<User>,
+        /// The link previews attached to the message with `!embed` blocks. See [`parse_posts`].
+        link_previews: Vec<LinkPreview>,
+    },
+}
+
+/// A preview card for a link, like the embeds that Discord and Twitter show under messages.
+///
+/// Cohoard never fetches anything from the network, so these are only ever made from `!embed`
+/// blocks written in the chatlog itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkPreview {
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<String>,
+    /// The name of the site. If not provided, this defaults to the domain of the `url`.
+    pub site: Option<String>,
 }
 
 /// Parse a chatlog of messages into a list of [`PostBlock`] objects.
@@ -43,7 +79,29 @@ pub enum ChatlogElement {
 /// @ Tomorrow on Wednesday
 /// C: The timestamp is freeform and can be any text.
 /// ```
-pub fn parse_posts(config: &Config, input: String) -> Vec<ChatlogElement> {
+///
+/// Messages can also have link previews attached to them, using an `!embed` line followed by
+/// any number of indented `field: value` lines. The fields are `title`, `description`, `image`,
+/// and `site`, and are all optional. These lines are removed from the message.
+/// ```no_compile
+/// A: have you seen this website
+/// !embed https://cohost.org
+///   title: cohost!
+///   description: posting, but better
+/// ```
+///
+/// At-macros (`@KEY.field`) in messages are expanded to the HTML-escaped value of the field. If the
+/// config has `strict_at_macros` set, an at-macro with an unknown key or field is an error.
+pub fn parse_posts(config: &Config, input: String) -> Result<Vec<ChatlogElement>, Box<dyn Error>> {
+    parse_posts_with_wrapper(config, input, &AtMacroWrapper::default())
+}
+
+// Parse a chatlog, wrapping at-macros with the given wrapper. See `parse_posts`.
+fn parse_posts_with_wrapper(
+    config: &Config,
+    input: String,
+    wrapper: &AtMacroWrapper,
+) -> Result<Vec<ChatlogElement>, Box<dyn Error>> {
     let mut posts = vec![];
 
     let mut prev_post: Option<(User, String)> = None;
@@ -54,8 +112,7 @@ pub fn parse_posts(config: &Config, input: String) -> Vec<ChatlogElement> {
         } else if line.starts_with("@") {
             // If there is a message already being constructed, finish it, then go on with the rest of the timestamp
             if let Some((user, message)) = prev_post {
-                let message = convert_at_macros(config, &message);
-                posts.push(ChatlogElement::Post { user, message });
+                posts.push(make_post(config, wrapper, user, &message)?);
                 prev_post = None;
             }
 
@@ -63,7 +120,7 @@ pub fn parse_posts(config: &Config, input: String) -> Vec<ChatlogElement> {
             // These have the format "@ Today at 4:13 PM" and update the timestamp
             // (The timestamp is actually freeform text, allowing for Goofs)
             let message = line[1..].trim().to_string();
-            let message = convert_at_macros(config, &message);
+            let message = at_macros::convert_at_macros(config, wrapper, &message)?;
             posts.push(ChatlogElement::Timestamp { message });
         } else {
             match line.split_once(": ") {
@@ -75,11 +132,10 @@ pub fn parse_posts(config: &Config, input: String) -> Vec<ChatlogElement> {
                 // compared to lines across different messages
                 Some((name, message)) if name.chars().all(|x| x.is_alphanumeric()) => {
                     if let Some((user, message)) = prev_post {
-                        let message = convert_at_macros(config, &message);
-                        posts.push(ChatlogElement::Post { user, message });
+                        posts.push(make_post(config, wrapper, user, &message)?);
                     }
 
-                    let user = get_user(config, &name);
+                    let user = config.get_user(name);
                     // Need to re-add new line explicitly, since `input.lines()` strips the newline.
                     prev_post = Some((user, format!("{}\n", message)))
                 }
@@ -95,24 +151,97 @@ pub fn parse_posts(config: &Config, input: String) -> Vec<ChatlogElement> {
     }
 
     if let Some((user, message)) = prev_post {
-        let message = convert_at_macros(config, &message);
-        posts.push(ChatlogElement::Post { user, message });
+        posts.push(make_post(config, wrapper, user, &message)?);
     }
 
-    fn get_user(config: &Config, name: &str) -> User {
-        config.people.get(name).cloned().unwrap_or({
-            User {
-                fields: [
-                    ("name".to_string(), name.to_string()),
-                    ("key".to_string(), name.to_string()),
-                ]
-                .into_iter()
-                .collect(),
-            }
+    fn make_post(
+        config: &Config,
+        wrapper: &AtMacroWrapper,
+        user: User,
+        message: &str,
+    ) -> Result<ChatlogElement, Box<dyn Error>> {
+        let (message, link_previews) = extract_link_previews(message);
+        let quirk = user.get_str("key").and_then(|key| config.quirks.get(key));
+        let message = quirks::apply_quirk(quirk, &message);
+        let message = at_macros::convert_at_macros(config, wrapper, &message)?;
+        let mentions = markdown::find_mentions(config, &message);
+        Ok(ChatlogElement::Post {
+            user,
+            message,
+            mentions,
+            link_previews,
         })
     }
 
-    posts
+    Ok(posts)
+}
+
+// Remove the `!embed` blocks from a message, returning the rest of the message and the link
+// previews described by the blocks. `!embed` lines inside of code blocks are left alone.
+fn extract_link_previews(message: &str) -> (String, Vec<LinkPreview>) {
+    let mut new_message = String::new();
+    let mut link_previews: Vec<LinkPreview> = vec![];
+    let mut in_code_block = false;
+    // Whether the previous line was part of an `!embed` block.
+    let mut in_embed = false;
+
+    for line in message.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if !in_code_block {
+            if let Some(url) = line.strip_prefix("!embed ") {
+                link_previews.push(LinkPreview {
+                    url: url.trim().to_string(),
+                    title: None,
+                    description: None,
+                    image: None,
+                    site: None,
+                });
+                in_embed = true;
+                continue;
+            }
+
+            if in_embed && line.starts_with(char::is_whitespace) {
+                let preview = link_previews.last_mut().unwrap();
+                let (field, value) = match line.trim().split_once(':') {
+                    Some(("title", value)) => (&mut preview.title, value),
+                    Some(("description", value)) => (&mut preview.description, value),
+                    Some(("image", value)) => (&mut preview.image, value),
+                    Some(("site", value)) => (&mut preview.site, value),
+                    _ => {
+                        new_message.push_str(line);
+                        new_message.push('\n');
+                        in_embed = false;
+                        continue;
+                    }
+                };
+                *field = Some(value.trim().to_string());
+                continue;
+            }
+        }
+
+        in_embed = false;
+        new_message.push_str(line);
+        new_message.push('\n');
+    }
+
+    // Default to the domain of the link if the site wasn't given.
+    for preview in &mut link_previews {
+        if preview.site.is_none() {
+            let domain = preview
+                .url
+                .split_once("://")
+                .map_or(preview.url.as_str(), |(_, rest)| rest);
+            let domain = domain.split('/').next().unwrap_or(domain);
+            if !domain.is_empty() {
+                preview.site = Some(domain.to_string());
+            }
+        }
+    }
+
+    (new_message, link_previews)
 }
 
 /// **DEPRECIATED**
@@ -161,16 +290,7 @@ impl OldPostBlock {
                 return;
             }
 
-            let user = config.people.get(name).cloned().unwrap_or({
-                User {
-                    fields: [
-                        ("name".to_string(), name.to_string()),
-                        ("key".to_string(), name.to_string()),
-                    ]
-                    .into_iter()
-                    .collect(),
-                }
-            });
+            let user = config.get_user(name);
 
             let post = OldPostBlock::new(user, timestamp, &messages);
             posts.push(post);
@@ -230,6 +350,28 @@ impl OldPostBlock {
 
         posts
     }
+
+    // Group already-parsed elements into post blocks, for chatlogs which weren't written in
+    // play-script style.
+    fn from_elements(elements: &[ChatlogElement]) -> Vec<OldPostBlock> {
+        group_postblocks(elements)
+            .into_iter()
+            .map(|block| {
+                let messages: Vec<String> = block
+                    .posts
+                    .iter()
+                    .filter_map(|post| match post {
+                        ChatlogElement::Post { message, .. } => {
+                            Some(message.trim_end_matches('\n').to_string())
+                        }
+                        ChatlogElement::Timestamp { .. } => None,
+                    })
+                    .collect();
+                let timestamp = Some(block.timestamp.to_string()).filter(|t| !t.is_empty());
+                OldPostBlock::new(block.user.clone(), timestamp, &messages)
+            })
+            .collect()
+    }
 }
 
 /// Render a slice of PostBlocks using the given Tera template.
@@ -237,24 +379,160 @@ impl OldPostBlock {
 /// `template_name` is cosmetic--this is simply used for error reporting and debugging.
 /// `template` should be contain the contents of the Tera template.
 /// `posts` is a list of ChatlogBlocks. This list should usually be produced by [`parse_posts`].
+///
+/// `partials` are other templates, as (name, contents) pairs, which the template can extend,
+/// include, or import by name, like `{% include "header.html" %}`. The built-in partials (see
+/// [`template::builtin_partials`]) are always available, but a partial with the same name replaces
+/// the built-in one.
+///
+/// Besides the usual Tera filters, templates can use the `markdown` filter to convert messages to
+/// HTML, and the `postblocks` filter to group `ELEMENTS` into blocks of posts like Discord does
+/// (see [`group_postblocks`]).
+///
+/// `additional_variables` are passed to the template as variables, and are usually the values of
+/// the template's controls (see [`template::parse_controls`]). Any control that isn't given a value
+/// is set to its default value.
+///
+/// If the template has an auto-colors block, like `{#-auto-colors #36393e auto-colors-#}`, and the
+/// config doesn't set `auto_colors`, speakers who aren't in the config get automatic colors, as if
+/// the config set `auto_colors` to the template's background. A `color` in the config's `defaults`
+/// still wins over these colors (see [`Config::get_user`]). Speakers with automatic colors are
+/// also included in the `users` variable, so that templates can style them like everyone else.
 pub fn render(
     template_name: &str,
     template: &str,
+    partials: &[(String, String)],
     chatlog: &str,
     config: &Config,
     additional_variables: impl IntoIterator<Item = (String, serde_json::Value)>,
 ) -> Result<String, Box<dyn Error>> {
+    render_with(
+        template_name,
+        template,
+        partials,
+        config,
+        additional_variables,
+        |config, wrapper| {
+            let elements = parse_posts_with_wrapper(config, chatlog.to_string(), wrapper)?;
+            let posts = OldPostBlock::parse_posts(config, chatlog.to_string());
+            Ok((elements, posts))
+        },
+    )
+}
+
+/// Render a chatlog written as JSON (see [`ast`]) using the given Tera template, instead of a
+/// chatlog written in play-script style. Otherwise, this is the same as [`render`].
+///
+/// Users referred to by key are looked up in the config, so speakers who aren't in the config get
+/// automatic colors just like in [`render`].
+pub fn render_elements(
+    template_name: &str,
+    template: &str,
+    partials: &[(String, String)],
+    chatlog_json: &str,
+    config: &Config,
+    additional_variables: impl IntoIterator<Item = (String, serde_json::Value)>,
+) -> Result<String, Box<dyn Error>> {
+    render_with(
+        template_name,
+        template,
+        partials,
+        config,
+        additional_variables,
+        |config, _| {
+            let elements = ast::import_elements(config, chatlog_json)?;
+            let posts = OldPostBlock::from_elements(&elements);
+            Ok((elements, posts))
+        },
+    )
+}
+
+// Render a template with the elements (and deprecated post blocks) made by `get_elements`, which
+// gets the config with automatic colors filled in and the template's at-macro wrapper. See
+// `render`.
+fn render_with(
+    template_name: &str,
+    template: &str,
+    partials: &[(String, String)],
+    config: &Config,
+    additional_variables: impl IntoIterator<Item = (String, serde_json::Value)>,
+    get_elements: impl FnOnce(
+        &Config,
+        &AtMacroWrapper,
+    ) -> Result<(Vec<ChatlogElement>, Vec<OldPostBlock>), Box<dyn Error>>,
+) -> Result<String, Box<dyn Error>> {
+    let mut additional_variables: Vec<(String, serde_json::Value)> =
+        additional_variables.into_iter().collect();
+    // Controls that weren't given a value get their default value, just like in the web UI. The
+    // web UI reports broken control blocks itself, so they don't stop the template from rendering.
+    for control in template::parse_controls(template).unwrap_or_default() {
+        if !additional_variables
+            .iter()
+            .any(|(name, _)| *name == control.name)
+        {
+            let value = control.default_value();
+            additional_variables.push((control.name, value));
+        }
+    }
+    let mut config = config.clone();
+    if config.auto_colors.is_none() {
+        let mut settings = Context::new();
+        for (name, value) in &additional_variables {
+            settings.insert(name, value);
+        }
+        config.auto_colors = template::parse_auto_colors(template, &settings)?;
+        config.auto_colors_from_template = config.auto_colors.is_some();
+    }
+    let config = &config;
+
     let mut tera = Tera::default();
-    tera.add_raw_template(template_name, template)?;
-    tera.register_filter("markdown", markdown_to_html);
+    // All of the templates need to be added at once, since a template can only extend a template
+    // which has already been added.
+    let mut templates = template::builtin_partials().to_vec();
+    templates.extend(
+        partials
+            .iter()
+            .map(|(name, contents)| (name.as_str(), contents.as_str())),
+    );
+    templates.push((template_name, template));
+    tera.add_raw_templates(templates)?;
+    let markdown_config = config.clone();
+    tera.register_filter(
+        "markdown",
+        move |value: &tera::Value, args: &HashMap<String, tera::Value>| {
+            markdown::markdown_to_html(&markdown_config, value, args)
+        },
+    );
+    tera.register_filter(
+        "postblocks",
+        |value: &tera::Value, _: &HashMap<String, tera::Value>| {
+            let elements: Vec<ChatlogElement> =
+                serde_json::from_value(value.clone()).map_err(|err| {
+                    format!("postblocks expects ELEMENTS, but got {}: {}", value, err)
+                })?;
+            Ok(tera::to_value(group_postblocks(&elements))?)
+        },
+    );
+
+    let wrapper = AtMacroWrapper::from_template(template)?;
+    let (elements, posts) = get_elements(config, &wrapper)?;
+    let mut users: Vec<&User> = config.people.values().collect();
+    if config.auto_colors.is_some() {
+        for element in &elements {
+            let ChatlogElement::Post { user, .. } = element else {
+                continue;
+            };
+            let key = user.get_str("key");
+            if !users.iter().any(|other| other.get_str("key") == key) {
+                users.push(user);
+            }
+        }
+    }
 
     let mut context = Context::new();
-    context.insert("ELEMENTS", &parse_posts(config, chatlog.to_string()));
-    context.insert(
-        "posts",
-        &OldPostBlock::parse_posts(config, chatlog.to_string()),
-    );
-    context.insert("users", &config.people.values().collect::<Vec<_>>());
+    context.insert("ELEMENTS", &elements);
+    context.insert("posts", &posts);
+    context.insert("users", &users);
     for (name, value) in additional_variables {
         context.insert(name, &value);
     }
@@ -282,6 +560,52 @@ pub fn render(
     Ok(html)
 }
 
+/// A block of posts by the same person, with no timestamps between them. See [`group_postblocks`].
+#[derive(Debug, Clone, Serialize)]
+pub struct PostBlock<'a> {
+    /// The most recent timestamp before the posts, or an empty string if there isn't one.
+    pub timestamp: &'a str,
+    pub user: &'a User,
+    /// The posts in the block, which are all [`ChatlogElement::Post`]s.
+    pub posts: Vec<&'a ChatlogElement>,
+}
+
+/// Group the elements of a chatlog into blocks of posts, like Discord does. A new block is started
+/// whenever the person posting changes, or after a timestamp. Templates can do this with
+/// `ELEMENTS | postblocks`.
+pub fn group_postblocks(elements: &[ChatlogElement]) -> Vec<PostBlock<'_>> {
+    let mut blocks: Vec<PostBlock> = vec![];
+    let mut timestamp = "";
+    // Whether the next post starts a new block, even if it's by the same person.
+    let mut new_block = true;
+
+    for element in elements {
+        match element {
+            ChatlogElement::Timestamp { message } => {
+                timestamp = message;
+                new_block = true;
+            }
+            ChatlogElement::Post { user, .. } => {
+                match blocks.last_mut() {
+                    Some(block)
+                        if !new_block && block.user.get_str("key") == user.get_str("key") =>
+                    {
+                        block.posts.push(element)
+                    }
+                    _ => blocks.push(PostBlock {
+                        timestamp,
+                        user,
+                        posts: vec![element],
+                    }),
+                }
+                new_block = false;
+            }
+        }
+    }
+
+    blocks
+}
+
 fn remove_class_and_id_attributes(document: &mut NodeRef) -> Result<(), ()> {
     for node in document.select("*")? {
         node.attributes.borrow_mut().remove("class");
@@ -301,96 +625,62 @@ fn inline_style_tags(html: &str) -> Result<String, InlineError> {
     inliner.inline(html)
 }
 
-// Convert a string-like tera::Value containing an at-macro into HTML
-// containing a span surrounding the expanded macro text.
-fn convert_at_macros(config: &Config, message: &str) -> String {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"@(?P<key>[A-Z]+)\.(?P<field>[[:alnum:]_]+)").unwrap();
-    }
-
-    RE.replace_all(message, |captures: &Captures| {
-        let key = &captures[1];
-        let field = &captures[2];
-        if let Some(user) = config.people.get(key) {
-            if let Some(field_value) = user.fields.get(field) {
-                return format!(
-                    "<span class=\"at-macro at-macro-{} at-macro-{}-{}\">{}</span>",
-                    key, key, field, field_value
-                );
-            }
-        }
-
-        captures[0].to_string()
-    })
-    .to_string()
-}
-
-// Convert a string-like tera::Value containing Markdown syntax into
-// HTML containing tags that correctly render the syntax.
-fn markdown_to_html(
-    value: &tera::Value,
-    _: &HashMap<String, tera::Value>,
-) -> tera::Result<tera::Value> {
-    let value = value.as_str().ok_or(tera::Error::msg(
-        "non-string value passed to markdown filter",
-    ))?;
-    let options = pulldown_cmark::Options::ENABLE_STRIKETHROUGH;
-    let parser = pulldown_cmark::Parser::new_ext(value, options);
-
-    let mut html = String::new();
-    html::push_html(&mut html, parser);
-    // pulldown-cmark adds <p> tags to the HTMLified text, which is undesirable since
-    // this also adds a large margin to all the text contained within it. We can fix this
-    // by simply removing all the <p> tags. This is safe and won't cause problems with
-    // literal textual tags in the message, since those will already be escaped.
-    // (and if they want an actual <p> tag, they should probably edit the HTML output of Cohoard,
-    // as most of the time, a raw <p> will look ugly).
-    let html = html.replace("<p>", "").replace("</p>", "");
-
-    // TODO: pulldown_cmark only understands __underscore__ as **bold**, so underline can only
-    // be done currently by using <u> tags.
-    // TODO: This find-and-replace nonsense should really be replaced by an actual HTML parser that
-    // can do actual replaces.
-
-    // Cohost doesn't accept <u>, so we need to replace it with a span that can be manually styled.
-    let html = html
-        .replace("<u>", r#"<span class="cohoard-underline">"#)
-        .replace("</u>", "</span>")
-        // pulldown_cmark encodes code blocks like this:
-        //  ```rust
-        //  my code block
-        //  ```
-        // as
-        // `<pre><code class=language-rust>my code block</code></pre>
-        // We will transform this into `<div class="cohoard-codeblock language-rust>my code block</div>`
-        // Note that if the language specifier is left off (ex: `rust` is left off in the example above)
-        // then pulldown_cmark does not add the `class=language-rust` attribute.
-        .replace(r#"<pre><code>"#, r#"<div class="cohoard-codeblock">"#)
-        // Note that the missing angle bracket in the transform below is a bit of a hack to allow us
-        // to not use a regex.
-        .replace(
-            r#"<pre><code class="language-"#,
-            r#"<div class="cohoard-codeblock language-"#,
-        )
-        .replace("</code></pre>", "</div>")
-        // Cohost attaches pseudo-elements to <code>, which can't be overriden with inline styling
-        // to get around this, we replace it with a span that we can manually style in a template.
-        .replace("<code>", r#"<span class="cohoard-code">"#)
-        .replace("</code>", "</span>");
-
-    Ok(tera::Value::String(html))
+#[test]
+fn test_link_previews() {
+    let (message, previews) = extract_link_previews(
+        "have you seen this website\n!embed https://cohost.org/rc/welcome\n  title: cohost!\n  \
+         description: posting: but better\n!embed https://example.com\n  not a field\n\
+         ```\n!embed https://not.an.embed\n```\n",
+    );
+    assert_eq!(
+        message,
+        "have you seen this website\n  not a field\n```\n!embed https://not.an.embed\n```\n"
+    );
+    assert_eq!(
+        previews,
+        [
+            LinkPreview {
+                url: "https://cohost.org/rc/welcome".to_string(),
+                title: Some("cohost!".to_string()),
+                description: Some("posting: but better".to_string()),
+                image: None,
+                site: Some("cohost.org".to_string()),
+            },
+            LinkPreview {
+                url: "https://example.com".to_string(),
+                title: None,
+                description: None,
+                image: None,
+                site: Some("example.com".to_string()),
+            },
+        ]
+    );
 }
 
 #[test]
-fn test_at_macro() {
-    let config = r##"people:
-    - key: JUICE
-      name: Juice
-    - key: TEN
-      handle: Ten
-  "##;
-    let config = config::load_config(config).unwrap();
-    let message = "@JUICE.name started pestering @TEN.handle";
-    let expected = "<span class=\"at-macro at-macro-JUICE at-macro-JUICE-name\">Juice</span> started pestering <span class=\"at-macro at-macro-TEN at-macro-TEN-handle\">Ten</span>";
-    assert_eq!(convert_at_macros(&config, message), expected);
+fn test_group_postblocks() {
+    let config = Config::default();
+    let elements = parse_posts(
+        &config,
+        "EGGBUG: hi\nEGGBUG: hello\nBUGEGG: hey\n@ Later\nBUGEGG: bye\n".to_string(),
+    )
+    .unwrap();
+    let blocks: Vec<(&str, Option<&str>, usize)> = group_postblocks(&elements)
+        .iter()
+        .map(|block| {
+            (
+                block.timestamp,
+                block.user.get_str("key"),
+                block.posts.len(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        blocks,
+        [
+            ("", Some("EGGBUG"), 2),
+            ("", Some("BUGEGG"), 1),
+            ("Later", Some("BUGEGG"), 1)
+        ]
+    );
 }
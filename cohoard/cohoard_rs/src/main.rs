@@ -1,65 +1,495 @@
-use std::{error::Error, io::Read, path::PathBuf};
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    io::Read,
+    path::{Path, PathBuf},
+};
 
 use clap::Parser as _;
 
 use cohoard_rs as cohoard;
 
-use cohoard::config;
+use cohoard::{
+    ast::{export_elements, UserStyle},
+    config, template, ChatlogElement,
+};
+use serde_json::Value;
 
+/// a chatlog formatter for cohost
+///
+/// If no subcommand is given, this renders the chatlog, like the `render` subcommand.
+///
+/// Exits with 0 on success, 1 if something went wrong (or if `check` found any problems), and 2
+/// if the arguments are invalid.
 #[derive(Debug, clap::Parser)]
-#[clap(author, version, about = "a chatlog formatter for cohost", long_about = None)]
-struct Args {
-    /// The file containing the chatlog.
-    in_file: Option<PathBuf>,
-    /// The configuration file to use.
+#[clap(author, version, args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(flatten)]
+    render: RenderArgs,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Render a chatlog with a template, printing only the HTML.
+    Render(RenderArgs),
+    /// Parse a chatlog, printing the elements that templates get.
+    Parse(ParseArgs),
+    /// Check a config, template, and chatlog for problems, without rendering anything.
+    Check(CheckArgs),
+    /// List or print the built-in templates.
+    Templates {
+        #[clap(subcommand)]
+        command: TemplatesCommand,
+    },
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum TemplatesCommand {
+    /// List the names and descriptions of the built-in templates, and the names of the built-in
+    /// partials.
+    List,
+    /// Print the contents of a built-in template or partial, such as to start a custom template
+    /// from it.
+    Show {
+        /// The name of the template or partial, like `discord` or `cohoard/markdown.css`.
+        name: String,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct ConfigArgs {
+    /// The configuration file to use. This can be a YAML, JSON, or TOML file. If this is given
+    /// more than once, the configs are merged, with later configs taking precedence.
     #[clap(long, short, default_value = "config.yaml")]
-    config: PathBuf,
+    config: Vec<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct TemplateArgs {
+    /// The template to use. This is either the name of a built-in template (see `templates list`)
+    /// or the path to a template file.
+    #[clap(long, short, default_value = "discord")]
+    template: String,
+    /// A directory of templates which the template can extend, include, or import, by their path
+    /// relative to the directory, like `{% include "header.html" %}`.
+    #[clap(long, value_name = "DIR")]
+    partials: Option<PathBuf>,
+    /// Set one of the template's options, like `--var light_mode=true`. The value is read as JSON
+    /// if it can be, and as text otherwise. This can be given more than once.
+    #[clap(long = "var", value_name = "NAME=VALUE")]
+    vars: Vec<String>,
+    /// A YAML or JSON file which sets the template's options, as a map from each option's name to
+    /// its value. Options set with `--var` take precedence over these.
+    #[clap(long = "vars", value_name = "FILE")]
+    var_files: Vec<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+struct RenderArgs {
+    /// The file containing the chatlog. If not given, the chatlog is read from standard input.
+    in_file: Option<PathBuf>,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    #[clap(flatten)]
+    template: TemplateArgs,
+    /// Read the chatlog as JSON, like the chatlogs printed by `parse --format json`, instead of
+    /// in play-script style.
+    #[clap(long)]
+    json: bool,
     /// The file to write the HTML file to, if provided. Otherwise, prints to standard out.
     #[clap(long = "out", short)]
     out_file: Option<PathBuf>,
-    /// The template file to use.
-    #[clap(long, short, default_value = "templates/discord.html")]
-    template: PathBuf,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
+#[derive(Debug, clap::Args)]
+struct ParseArgs {
+    /// The file containing the chatlog. If not given, the chatlog is read from standard input.
+    in_file: Option<PathBuf>,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    /// How to print the elements.
+    #[clap(long, short, arg_enum, default_value = "text")]
+    format: ParseFormat,
+    /// When printing JSON or YAML, write only the key of each person instead of all of their
+    /// fields, so that they're looked up in the config when the chatlog is rendered.
+    #[clap(long)]
+    user_keys: bool,
+}
 
-    let config = std::fs::read_to_string(args.config)?;
-    let config = config::load_config(&config)?;
+#[derive(Debug, Clone, Copy, clap::ArgEnum)]
+enum ParseFormat {
+    /// The elements as versioned JSON, which `render --json` can read back.
+    Json,
+    /// The same as `json`, but written as YAML.
+    Yaml,
+    /// A summary of the elements, written like a chatlog.
+    Text,
+}
 
-    let input = if let Some(path) = args.in_file {
-        std::fs::read_to_string(path)?
-    } else {
-        let mut string = String::new();
-        std::io::stdin().read_to_string(&mut string)?;
-        string
+#[derive(Debug, clap::Args)]
+struct CheckArgs {
+    /// The file containing the chatlog. If given, the chatlog is also parsed and rendered to check
+    /// for problems in it, such as unknown at-macros in strict mode. Otherwise, the template is
+    /// rendered with an empty chatlog.
+    in_file: Option<PathBuf>,
+    #[clap(flatten)]
+    config: ConfigArgs,
+    #[clap(flatten)]
+    template: TemplateArgs,
+    /// Also print where each field of each person in the config came from.
+    #[clap(long)]
+    explain_config: bool,
+    /// Also print the options that the template has, along with their default values. These are
+    /// the same options that the web UI shows.
+    #[clap(long)]
+    list_options: bool,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let result = match cli.command {
+        Some(Command::Render(args)) => render(args),
+        Some(Command::Parse(args)) => parse(args),
+        Some(Command::Check(args)) => check(args),
+        Some(Command::Templates { command }) => templates(command),
+        None => render(cli.render),
     };
 
-    let posts = cohoard::parse_posts(&config, input.clone());
-    for post in &posts {
-        match post {
-            cohoard::ChatlogElement::Timestamp { message } => println!("TIMESTAMP: {}", message),
-            cohoard::ChatlogElement::Post { user: _, message } => {
-                println!("POST: {}", message)
-            }
-        }
+    if let Err(err) = result {
+        eprintln!("error: {}", full_message(err.as_ref()));
+        std::process::exit(1);
     }
+}
 
-    let template_contents = std::fs::read_to_string(&args.template)?;
-    let html = cohoard::render(
-        args.template.to_str().unwrap_or("template"),
-        &template_contents,
+fn render(args: RenderArgs) -> Result<(), Box<dyn Error>> {
+    let config = load_configs(&args.config)?;
+    let input = read_chatlog(args.in_file.as_deref())?;
+    let template = load_template(&args.template)?;
+    let variables = template_variables(&args.template, &template.source)?;
+
+    let render = if args.json {
+        cohoard::render_elements
+    } else {
+        cohoard::render
+    };
+    let html = render(
+        &template.name,
+        &template.source,
+        &template.partials,
         &input,
         &config,
-        std::iter::empty(),
+        variables,
     )?;
 
-    if let Some(out_path) = args.out_file {
-        std::fs::write(out_path, html)?;
+    if let Some(out_path) = &args.out_file {
+        std::fs::write(out_path, html)
+            .map_err(|err| format!("Couldn't write {}: {}", out_path.display(), err))?;
     } else {
         println!("{}", html);
     }
+    Ok(())
+}
+
+fn parse(args: ParseArgs) -> Result<(), Box<dyn Error>> {
+    let config = load_configs(&args.config)?;
+    let input = read_chatlog(args.in_file.as_deref())?;
+    let elements = cohoard::parse_posts(&config, input)?;
+    let style = if args.user_keys {
+        UserStyle::Keys
+    } else {
+        UserStyle::Resolved
+    };
+
+    match args.format {
+        ParseFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&export_elements(&elements, style))?
+        ),
+        ParseFormat::Yaml => print!(
+            "{}",
+            serde_yaml::to_string(&export_elements(&elements, style))?
+        ),
+        ParseFormat::Text => {
+            for element in &elements {
+                println!("{}", describe_element(element));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check(args: CheckArgs) -> Result<(), Box<dyn Error>> {
+    let config = load_configs(&args.config)?;
+    let LoadedTemplate {
+        name,
+        source: template,
+        partials,
+    } = load_template(&args.template)?;
 
+    if args.explain_config {
+        println!("{}", config.explain());
+    }
+    if args.list_options {
+        for control in template::parse_controls(&template)? {
+            println!("{}", describe_control(&control)?);
+        }
+    }
+
+    let mut problems = vec![];
+    match template::validate_config(&template, &config) {
+        Ok(config_problems) => problems.extend(config_problems.iter().map(|p| p.to_string())),
+        Err(err) => problems.push(full_message(err.as_ref())),
+    }
+    let variables = match template_variables(&args.template, &template) {
+        Ok(variables) => variables,
+        Err(err) => {
+            problems.push(full_message(err.as_ref()));
+            vec![]
+        }
+    };
+    let input = match &args.in_file {
+        Some(path) => read_file(path)?,
+        None => String::new(),
+    };
+    if let Err(err) = cohoard::render(&name, &template, &partials, &input, &config, variables) {
+        problems.push(full_message(err.as_ref()));
+    }
+
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if !problems.is_empty() {
+        return Err(format!("Found {} problem(s)", problems.len()).into());
+    }
+    eprintln!("No problems found");
     Ok(())
 }
+
+fn templates(command: TemplatesCommand) -> Result<(), Box<dyn Error>> {
+    match command {
+        TemplatesCommand::List => {
+            let templates = template::builtin_templates();
+            let width = templates.iter().map(|t| t.name.len()).max().unwrap_or(0);
+            for template in templates {
+                println!(
+                    "{:width$}  {}: {}",
+                    template.name,
+                    template.title,
+                    template.description,
+                    width = width
+                );
+            }
+            println!();
+            println!("Partials, which templates can extend, include, or import:");
+            for (name, _) in template::builtin_partials() {
+                println!("{}", name);
+            }
+        }
+        TemplatesCommand::Show { name } => {
+            let partial = template::builtin_partials()
+                .iter()
+                .find(|(partial, _)| *partial == name)
+                .map(|(_, source)| *source);
+            let source = template::get_builtin_template(&name)
+                .map(|template| template.source)
+                .or(partial)
+                .ok_or_else(|| format!("There is no built-in template called \"{}\"", name))?;
+            print!("{}", source);
+        }
+    }
+    Ok(())
+}
+
+// A template to render, along with the partials it can use.
+struct LoadedTemplate {
+    name: String,
+    source: String,
+    partials: Vec<(String, String)>,
+}
+
+// Load the template to use. Names of built-in templates take precedence over paths.
+fn load_template(args: &TemplateArgs) -> Result<LoadedTemplate, Box<dyn Error>> {
+    let partials = match &args.partials {
+        Some(dir) => load_partials(dir, dir)?,
+        None => vec![],
+    };
+    if let Some(template) = template::get_builtin_template(&args.template) {
+        return Ok(LoadedTemplate {
+            name: template.name.to_string(),
+            source: template.source.to_string(),
+            partials,
+        });
+    }
+    let path = Path::new(&args.template);
+    if !path.exists() {
+        return Err(format!(
+            "There is no built-in template or template file called \"{}\" (see `templates list` \
+             for the built-in templates)",
+            args.template
+        )
+        .into());
+    }
+    Ok(LoadedTemplate {
+        name: args.template.clone(),
+        source: read_file(path)?,
+        partials,
+    })
+}
+
+// Load every file in a directory (and its subdirectories) as a partial, named by its path relative
+// to `root`, with `/` between directories.
+fn load_partials(root: &Path, dir: &Path) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("Couldn't read {}: {}", dir.display(), err))?;
+    let mut partials = vec![];
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            partials.extend(load_partials(root, &path)?);
+            continue;
+        }
+        let name: Vec<String> = path
+            .strip_prefix(root)?
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        partials.push((name.join("/"), read_file(&path)?));
+    }
+    Ok(partials)
+}
+
+// Get an error message along with the messages of all of the errors that caused it, since the
+// messages of some errors (like Tera's) don't say much on their own.
+fn full_message(mut err: &dyn Error) -> String {
+    let mut messages = vec![err.to_string()];
+    while let Some(source) = err.source() {
+        err = source;
+        messages.push(err.to_string());
+    }
+    messages.join(": ")
+}
+
+// Read a file, saying which file couldn't be read on error.
+fn read_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    std::fs::read_to_string(path)
+        .map_err(|err| format!("Couldn't read {}: {}", path.display(), err).into())
+}
+
+// Read the chatlog from the given file, or from standard input if there isn't one.
+fn read_chatlog(path: Option<&Path>) -> Result<String, Box<dyn Error>> {
+    match path {
+        Some(path) => read_file(path),
+        None => {
+            let mut string = String::new();
+            std::io::stdin().read_to_string(&mut string)?;
+            Ok(string)
+        }
+    }
+}
+
+// Load and merge all of the config files.
+fn load_configs(args: &ConfigArgs) -> Result<config::Config, Box<dyn Error>> {
+    let mut config = config::Config::default();
+    for path in &args.config {
+        config.merge(load_config_file(path)?);
+    }
+    Ok(config)
+}
+
+// Get the template variables set with `--vars` and `--var`, checking them against the template's
+// controls.
+fn template_variables(
+    args: &TemplateArgs,
+    template: &str,
+) -> Result<Vec<(String, Value)>, Box<dyn Error>> {
+    let mut variables = BTreeMap::new();
+    for path in &args.var_files {
+        let contents = read_file(path)?;
+        let file: BTreeMap<String, Value> = serde_yaml::from_str(&contents)
+            .map_err(|err| format!("Couldn't read the options in {}: {}", path.display(), err))?;
+        variables.extend(file);
+    }
+    for var in &args.vars {
+        let Some((name, value)) = var.split_once('=') else {
+            return Err(format!(
+                "Expected --var to look like NAME=VALUE, but got \"{}\"",
+                var
+            )
+            .into());
+        };
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+        variables.insert(name.trim().to_string(), value);
+    }
+
+    let controls = template::parse_controls(template)?;
+    let mut checked = vec![];
+    for (name, value) in variables {
+        let Some(control) = controls.iter().find(|control| control.name == name) else {
+            let names: Vec<&str> = controls
+                .iter()
+                .map(|control| control.name.as_str())
+                .collect();
+            let options = if names.is_empty() {
+                "the template doesn't have any options".to_string()
+            } else {
+                format!("the template's options are {}", names.join(", "))
+            };
+            return Err(format!("Unknown template option \"{}\": {}", name, options).into());
+        };
+        checked.push((name, control.check_value(value)?));
+    }
+    Ok(checked)
+}
+
+// Describe a chatlog element in the same style as a chatlog, with the continued lines of a
+// message indented.
+fn describe_element(element: &ChatlogElement) -> String {
+    match element {
+        ChatlogElement::Timestamp { message } => format!("@ {}", message),
+        ChatlogElement::Post {
+            user,
+            message,
+            link_previews,
+            ..
+        } => {
+            let mut text = format!(
+                "{}: {}",
+                user.get_str("key").unwrap_or_default(),
+                message.trim_end().replace('\n', "\n  ")
+            );
+            for preview in link_previews {
+                text.push_str(&format!("\n!embed {}", preview.url));
+            }
+            text
+        }
+    }
+}
+
+// Describe a template control on one line, like `light_mode (checkbox): Use Light Mode [default:
+// false]`.
+fn describe_control(control: &template::Control) -> Result<String, Box<dyn Error>> {
+    let kind = serde_json::to_value(control.kind)?;
+    let mut line = format!("{} ({})", control.name, kind.as_str().unwrap_or_default());
+    if !control.label.is_empty() {
+        line.push_str(&format!(": {}", control.label));
+    }
+    line.push_str(&format!(" [default: {}]", control.default_value()));
+    Ok(line)
+}
+
+// Load a config file, using the file extension to determine the format.
+fn load_config_file(path: &Path) -> Result<config::Config, Box<dyn Error>> {
+    let format = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(config::ConfigFormat::from_extension);
+    let contents = read_file(path)?;
+    let mut config = match format {
+        Some(format) => config::load_config_as(&contents, format)?,
+        None => config::load_config(&contents)?,
+    };
+    config.set_file(&path.display().to_string());
+    Ok(config)
+}
@@ -0,0 +1,1288 @@
+use std::{borrow::Cow, collections::HashMap, ops::Range};
+
+use lazy_static::lazy_static;
+use pulldown_cmark::{
+    escape::{escape_href, escape_html},
+    html, CodeBlockKind, Event, LinkType, Tag,
+};
+use regex::{Captures, Regex};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Theme, ThemeSet},
+    html::{styled_line_to_highlighted_html, IncludeBackground},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use crate::{config::Config, User};
+
+const UNDERLINE_START: &str = r#"<span class="cohoard-underline">"#;
+
+lazy_static! {
+    static ref SYNTAX_SET: SyntaxSet = SyntaxSet::load_defaults_newlines();
+    static ref THEME_SET: ThemeSet = ThemeSet::load_defaults();
+    // Matches Discord-style `<@KEY>` and `@KEY` mentions. The character before the mention is
+    // captured so that things like email addresses aren't treated as mentions, and a `.field`
+    // after the mention is captured so that at-macros which didn't expand aren't either.
+    static ref MENTION_RE: Regex =
+        Regex::new(r"(^|\W)(?:<@(?P<bracketed>\w+)>|@(?P<key>\w+)(?P<field>\.\w+)?)").unwrap();
+    // Matches bare http(s) URLs. Trailing punctuation is trimmed off afterwards.
+    static ref URL_RE: Regex = Regex::new(r#"https?://[^\s<>"]+"#).unwrap();
+    // Matches `:shortcode:` emoji and Discord's `<:name:id>` (or `<a:name:id>` if animated) custom emoji.
+    static ref EMOJI_RE: Regex =
+        Regex::new(r":(?P<shortcode>[\w+-]+):|<(?P<animated>a?):(?P<name>\w+):(?P<id>\d+)>").unwrap();
+}
+
+// Discord only makes emoji bigger if the message has at most this many emoji.
+const MAX_JUMBO_EMOJI: usize = 27;
+
+/// The flavor of Markdown that the `markdown` filter understands. Templates select the dialect
+/// with the `dialect` argument, for example `message | markdown(dialect="discord")`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MarkdownDialect {
+    /// Plain CommonMark (plus strikethrough). `__text__` is bold. This is the default.
+    CommonMark,
+    /// Discord-flavored Markdown. Same as CommonMark, except that `__text__` is underlined, quotes
+    /// work like they do in Discord, and `-# subtext` is enabled.
+    Discord,
+}
+
+/// The options for the `markdown` filter. Templates set these by passing arguments to the filter,
+/// for example `message | markdown(dialect="discord", tables=true)`. Options which aren't passed to
+/// the filter are set based on the dialect.
+#[derive(Debug, Clone)]
+struct MarkdownOptions {
+    dialect: MarkdownDialect,
+    /// The theme used to syntax highlight code blocks. If None, code blocks aren't highlighted.
+    code_theme: Option<&'static Theme>,
+    /// GitHub-style tables.
+    tables: bool,
+    /// GitHub-style `- [x]` task lists.
+    tasklists: bool,
+    /// `[^note]` style footnotes.
+    footnotes: bool,
+    /// `# Heading` style headings. If disabled, headings are left as plain text.
+    headings: bool,
+    /// Discord-style quotes, where `> ` quotes a single line and `>>> ` quotes the rest of the
+    /// message. If disabled, quotes follow the usual CommonMark rules instead.
+    discord_quotes: bool,
+    /// Discord-style `-# subtext`, which makes a line of text smaller.
+    subtext: bool,
+    /// Discord-style `@KEY` and `<@KEY>` mentions of users in the config.
+    mentions: bool,
+    /// Turn bare URLs into links.
+    autolinks: bool,
+    /// `:shortcode:` emoji (either custom emoji from the config or Unicode emoji) and Discord's
+    /// `<:name:id>` custom emoji.
+    emoji: bool,
+}
+
+impl MarkdownOptions {
+    const ARGUMENTS: [&'static str; 11] = [
+        "dialect",
+        "code_theme",
+        "tables",
+        "tasklists",
+        "footnotes",
+        "headings",
+        "discord_quotes",
+        "subtext",
+        "mentions",
+        "autolinks",
+        "emoji",
+    ];
+
+    fn from_args(args: &HashMap<String, tera::Value>) -> tera::Result<MarkdownOptions> {
+        if let Some(name) = args
+            .keys()
+            .find(|name| !MarkdownOptions::ARGUMENTS.contains(&name.as_str()))
+        {
+            return Err(tera::Error::msg(format!(
+                "unknown argument `{}` passed to markdown filter (expected one of: {})",
+                name,
+                MarkdownOptions::ARGUMENTS.join(", ")
+            )));
+        }
+
+        let dialect = match string_arg(args, "dialect")? {
+            None | Some("commonmark") => MarkdownDialect::CommonMark,
+            Some("discord") => MarkdownDialect::Discord,
+            Some(dialect) => {
+                return Err(tera::Error::msg(format!(
+                    "unknown markdown dialect \"{}\" (expected \"commonmark\" or \"discord\")",
+                    dialect
+                )))
+            }
+        };
+        let is_discord = dialect == MarkdownDialect::Discord;
+
+        // Get the syntax highlighting theme. The available themes are the ones bundled with
+        // syntect (ex: "base16-ocean.dark", "InspiredGitHub").
+        let code_theme = match string_arg(args, "code_theme")? {
+            None => None,
+            Some(theme_name) => match THEME_SET.themes.get(theme_name) {
+                Some(theme) => Some(theme),
+                None => {
+                    return Err(tera::Error::msg(format!(
+                        "unknown code theme \"{}\" (expected one of: {})",
+                        theme_name,
+                        THEME_SET
+                            .themes
+                            .keys()
+                            .map(|name| format!("\"{}\"", name))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )))
+                }
+            },
+        };
+
+        Ok(MarkdownOptions {
+            dialect,
+            code_theme,
+            tables: bool_arg(args, "tables", false)?,
+            tasklists: bool_arg(args, "tasklists", false)?,
+            footnotes: bool_arg(args, "footnotes", false)?,
+            headings: bool_arg(args, "headings", true)?,
+            discord_quotes: bool_arg(args, "discord_quotes", is_discord)?,
+            subtext: bool_arg(args, "subtext", is_discord)?,
+            mentions: bool_arg(args, "mentions", is_discord)?,
+            autolinks: bool_arg(args, "autolinks", true)?,
+            emoji: bool_arg(args, "emoji", true)?,
+        })
+    }
+
+    fn parser_options(&self) -> pulldown_cmark::Options {
+        let mut options = pulldown_cmark::Options::ENABLE_STRIKETHROUGH;
+        options.set(pulldown_cmark::Options::ENABLE_TABLES, self.tables);
+        options.set(pulldown_cmark::Options::ENABLE_TASKLISTS, self.tasklists);
+        options.set(pulldown_cmark::Options::ENABLE_FOOTNOTES, self.footnotes);
+        options
+    }
+}
+
+fn string_arg<'a>(
+    args: &'a HashMap<String, tera::Value>,
+    name: &str,
+) -> tera::Result<Option<&'a str>> {
+    match args.get(name) {
+        None => Ok(None),
+        Some(value) => value.as_str().map(Some).ok_or(tera::Error::msg(format!(
+            "non-string value passed to markdown filter's `{}` argument",
+            name
+        ))),
+    }
+}
+
+fn bool_arg(args: &HashMap<String, tera::Value>, name: &str, default: bool) -> tera::Result<bool> {
+    match args.get(name) {
+        None => Ok(default),
+        Some(value) => value.as_bool().ok_or(tera::Error::msg(format!(
+            "non-boolean value passed to markdown filter's `{}` argument",
+            name
+        ))),
+    }
+}
+
+// Convert a string-like tera::Value containing Markdown syntax into
+// HTML containing tags that correctly render the syntax.
+pub(crate) fn markdown_to_html(
+    config: &Config,
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let value = value.as_str().ok_or(tera::Error::msg(
+        "non-string value passed to markdown filter",
+    ))?;
+    let options = MarkdownOptions::from_args(args)?;
+
+    let source = if options.discord_quotes {
+        convert_discord_quotes(value)
+    } else {
+        value.to_string()
+    };
+    let parser =
+        pulldown_cmark::Parser::new_ext(&source, options.parser_options()).into_offset_iter();
+    let events = match options.dialect {
+        MarkdownDialect::CommonMark => convert_spoilers(parser.map(|(event, _)| event)),
+        MarkdownDialect::Discord => convert_spoilers(convert_underlines(&source, parser)),
+    };
+    let events = if options.subtext {
+        convert_subtext(events)
+    } else {
+        events
+    };
+    let events = if options.autolinks {
+        convert_autolinks(events)
+    } else {
+        events
+    };
+    let events = if options.mentions {
+        convert_mentions(config, events)
+    } else {
+        events
+    };
+    let events = if options.emoji {
+        convert_emoji(config, events)
+    } else {
+        events
+    };
+    let events = match options.code_theme {
+        Some(theme) => highlight_code_blocks(events, theme)?,
+        None => events,
+    };
+    let events = convert_cohost_tags(events, &options);
+
+    let mut html = String::new();
+    html::push_html(&mut html, events.into_iter());
+
+    // Like Discord, messages consisting of only a few emoji get bigger emoji. The size of custom
+    // emoji is relative to the font size, so making the text bigger makes both kinds bigger.
+    if options.emoji && is_emoji_only(config, value) {
+        html = format!(
+            r#"<span class="cohoard-emoji-jumbo" style="font-size: 2.2em; line-height: 1.2;">{}</span>"#,
+            html.trim()
+        );
+    }
+    Ok(tera::Value::String(html))
+}
+
+// Rewrite Discord-style quotes into CommonMark quotes. In Discord, a `> ` only quotes the line it
+// is on, and `>>> ` quotes everything up to the end of the message. CommonMark instead lets a quote
+// continue onto the following lines (a "lazy continuation line"), so we stop that by inserting a
+// blank line after the quote. Lines that start with `>` but no space aren't quotes in Discord, so
+// those get escaped. Code blocks are left alone.
+fn convert_discord_quotes(source: &str) -> String {
+    let mut output = String::new();
+    let mut in_code_block = false;
+    let mut prev_line_quoted = false;
+
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+        }
+
+        if !in_code_block {
+            if let Some(rest) = line.strip_prefix(">>> ") {
+                output.push_str("> ");
+                output.push_str(rest);
+                output.push('\n');
+                for line in lines.by_ref() {
+                    output.push_str("> ");
+                    output.push_str(line);
+                    output.push('\n');
+                }
+                break;
+            }
+
+            let quoted = line.starts_with("> ");
+            if prev_line_quoted && !quoted {
+                output.push('\n');
+            } else if line.starts_with('>') && !quoted {
+                output.push('\\');
+            }
+            prev_line_quoted = quoted;
+        }
+
+        output.push_str(line);
+        output.push('\n');
+    }
+    output
+}
+
+// Convert Discord-style `-# subtext` lines into a span that templates can make smaller.
+fn convert_subtext(events: Vec<Event>) -> Vec<Event> {
+    let mut output = vec![];
+    // Whether the next event starts a new line of text.
+    let mut at_line_start = false;
+    let mut in_subtext = false;
+
+    for event in events {
+        match event {
+            Event::Text(text) if at_line_start && text.starts_with("-# ") => {
+                output.push(Event::Html(r#"<span class="cohoard-subtext">"#.into()));
+                output.push(Event::Text(text["-# ".len()..].to_string().into()));
+                in_subtext = true;
+                at_line_start = false;
+            }
+            Event::Start(Tag::Paragraph) => {
+                at_line_start = true;
+                output.push(event);
+            }
+            Event::SoftBreak | Event::HardBreak | Event::End(Tag::Paragraph) => {
+                if in_subtext {
+                    output.push(Event::Html("</span>".into()));
+                    in_subtext = false;
+                }
+                at_line_start = !matches!(event, Event::End(_));
+                output.push(event);
+            }
+            _ => {
+                at_line_start = false;
+                output.push(event);
+            }
+        }
+    }
+    output
+}
+
+// Turn bare URLs into links. The links are written out as HTML immediately, so that later passes
+// (such as mentions and emoji) don't mess with the URL. URLs which are already inside of a link or
+// a code block are left alone.
+fn convert_autolinks(events: Vec<Event>) -> Vec<Event> {
+    let mut output = vec![];
+    let mut in_link_or_code = false;
+    for event in events {
+        let text = match event {
+            Event::Start(Tag::Link(..)) | Event::Start(Tag::CodeBlock(_)) => {
+                in_link_or_code = true;
+                output.push(event);
+                continue;
+            }
+            Event::End(Tag::Link(..)) | Event::End(Tag::CodeBlock(_)) => {
+                in_link_or_code = false;
+                output.push(event);
+                continue;
+            }
+            Event::Text(text) if !in_link_or_code => text,
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut last_end = 0;
+        for url in URL_RE.find_iter(&text) {
+            // Punctuation at the end of a URL is more likely to be part of the sentence than part
+            // of the URL. Closing parentheses are kept if they match an opening one in the URL,
+            // for links like https://en.wikipedia.org/wiki/Cohost_(website).
+            let mut url_text = url.as_str();
+            loop {
+                let trimmed = url_text.trim_end_matches(|c: char| {
+                    matches!(
+                        c,
+                        '.' | ',' | ':' | ';' | '!' | '?' | '\'' | '*' | '_' | '~'
+                    )
+                });
+                let trimmed = match trimmed.strip_suffix(')') {
+                    Some(rest) if rest.matches('(').count() < trimmed.matches(')').count() => rest,
+                    _ => trimmed,
+                };
+                if trimmed.len() == url_text.len() {
+                    break;
+                }
+                url_text = trimmed;
+            }
+            if url_text.len() <= "https://".len() {
+                continue;
+            }
+
+            if last_end < url.start() {
+                output.push(Event::Text(text[last_end..url.start()].to_string().into()));
+            }
+            let mut html = r#"<a href=""#.to_string();
+            escape_href(&mut html, url_text).unwrap();
+            html.push_str(r#"">"#);
+            escape_html(&mut html, url_text).unwrap();
+            html.push_str("</a>");
+            output.push(Event::Html(html.into()));
+            last_end = url.start() + url_text.len();
+        }
+
+        if last_end == 0 {
+            output.push(Event::Text(text));
+        } else if last_end < text.len() {
+            output.push(Event::Text(text[last_end..].to_string().into()));
+        }
+    }
+    output
+}
+
+// Find the users mentioned in a message. Mentions inside of code aren't counted.
+pub(crate) fn find_mentions(config: &Config, message: &str) -> Vec<User> {
+    let mut keys: Vec<&str> = vec![];
+    let mut in_code_block = false;
+    for event in pulldown_cmark::Parser::new(message) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(text) if !in_code_block => {
+                for captures in MENTION_RE.captures_iter(&text) {
+                    if let Some((key, _)) = mentioned_user(config, &captures) {
+                        if !keys.contains(&key) {
+                            keys.push(key);
+                        }
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    keys.into_iter()
+        .map(|key| config.people[key].clone())
+        .collect()
+}
+
+// Get the key and user of a match of MENTION_RE, if it is actually a mention of a user in the config.
+fn mentioned_user<'a>(config: &'a Config, captures: &Captures) -> Option<(&'a str, &'a User)> {
+    if captures.name("field").is_some() {
+        return None;
+    }
+    let key = captures
+        .name("bracketed")
+        .or_else(|| captures.name("key"))?
+        .as_str();
+    config
+        .people
+        .get_key_value(key)
+        .map(|(key, user)| (key.as_str(), user))
+}
+
+// Convert `@KEY` and `<@KEY>` mentions of users in the config into mention pills, which show the
+// name of the user. Templates can style mentions of specific users with the
+// `cohoard-mention-KEY` class.
+fn convert_mentions<'a>(config: &Config, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut output = vec![];
+    // pulldown_cmark parses `<@KEY>` as an email autolink to "@KEY". Since the text of the link is
+    // "@KEY", it gets turned into a mention anyways, so only the link itself needs to be removed.
+    let mut in_mention_link = false;
+    let mut in_code_block = false;
+    for event in events {
+        let text = match event {
+            Event::Text(text) if !in_code_block => text,
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                output.push(event);
+                continue;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                output.push(event);
+                continue;
+            }
+            Event::Start(Tag::Link(LinkType::Email, ref url, _))
+                if url
+                    .strip_prefix('@')
+                    .is_some_and(|key| config.people.contains_key(key)) =>
+            {
+                in_mention_link = true;
+                continue;
+            }
+            Event::End(Tag::Link(..)) if in_mention_link => {
+                in_mention_link = false;
+                continue;
+            }
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut last_end = 0;
+        for captures in MENTION_RE.captures_iter(&text) {
+            if let Some((key, user)) = mentioned_user(config, &captures) {
+                // Don't include the character before the mention.
+                let start = captures.get(1).unwrap().end();
+                let end = captures.get(0).unwrap().end();
+                if last_end < start {
+                    output.push(Event::Text(text[last_end..start].to_string().into()));
+                }
+
+                let mut html =
+                    format!(r#"<span class="cohoard-mention cohoard-mention-{}">@"#, key);
+                escape_html(&mut html, user.get_str("name").unwrap_or(key)).unwrap();
+                html.push_str("</span>");
+                output.push(Event::Html(html.into()));
+                last_end = end;
+            }
+        }
+
+        if last_end == 0 {
+            output.push(Event::Text(text));
+        } else if last_end < text.len() {
+            output.push(Event::Text(text[last_end..].to_string().into()));
+        }
+    }
+    output
+}
+
+enum Emoji<'a> {
+    Unicode(&'static str),
+    Image { name: String, url: Cow<'a, str> },
+}
+
+// Get the image URL (for custom emoji) or text (for Unicode emoji) for a match of EMOJI_RE, if the
+// emoji exists. Custom emoji from the config take priority over Unicode emoji.
+fn lookup_emoji<'a>(config: &'a Config, captures: &Captures) -> Option<Emoji<'a>> {
+    if let Some(shortcode) = captures.name("shortcode") {
+        let shortcode = shortcode.as_str();
+        if let Some(url) = config.emoji.get(shortcode) {
+            Some(Emoji::Image {
+                name: shortcode.to_string(),
+                url: url.into(),
+            })
+        } else {
+            emojis::get_by_shortcode(shortcode).map(|emoji| Emoji::Unicode(emoji.as_str()))
+        }
+    } else {
+        let extension = if captures["animated"].is_empty() {
+            "png"
+        } else {
+            "gif"
+        };
+        Some(Emoji::Image {
+            name: captures["name"].to_string(),
+            url: format!(
+                "https://cdn.discordapp.com/emojis/{}.{}",
+                &captures["id"], extension
+            )
+            .into(),
+        })
+    }
+}
+
+// Expand `:shortcode:` emoji and Discord's `<:name:id>` custom emoji. Unicode emoji are replaced
+// with the emoji itself, while custom emoji become an image sized to match the text around it.
+// The image is sized with an inline style, since Cohost makes images display as full-size blocks.
+// Code blocks are left alone.
+fn convert_emoji<'a>(config: &Config, events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut output = vec![];
+    let mut in_code_block = false;
+    for event in events {
+        let text = match event {
+            Event::Text(text) if !in_code_block => text,
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                output.push(event);
+                continue;
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                in_code_block = false;
+                output.push(event);
+                continue;
+            }
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut last_end = 0;
+        for captures in EMOJI_RE.captures_iter(&text) {
+            let emoji = match lookup_emoji(config, &captures) {
+                Some(emoji) => emoji,
+                None => continue,
+            };
+            let range = captures.get(0).unwrap().range();
+            if last_end < range.start {
+                output.push(Event::Text(text[last_end..range.start].to_string().into()));
+            }
+            match emoji {
+                Emoji::Unicode(emoji) => output.push(Event::Text(emoji.into())),
+                Emoji::Image { name, url } => {
+                    let mut html = r#"<img class="cohoard-emoji" src=""#.to_string();
+                    escape_href(&mut html, &url).unwrap();
+                    html.push_str(r#"" alt=":"#);
+                    escape_html(&mut html, &name).unwrap();
+                    html.push_str(r#":" title=":"#);
+                    escape_html(&mut html, &name).unwrap();
+                    html.push_str(
+                        r#":" style="display: inline; height: 1.375em; width: auto; margin: 0; vertical-align: bottom;">"#,
+                    );
+                    output.push(Event::Html(html.into()));
+                }
+            }
+            last_end = range.end;
+        }
+
+        if last_end == 0 {
+            output.push(Event::Text(text));
+        } else if last_end < text.len() {
+            output.push(Event::Text(text[last_end..].to_string().into()));
+        }
+    }
+    output
+}
+
+// Check if a message consists of nothing but emoji (and whitespace), and has at most
+// MAX_JUMBO_EMOJI of them.
+fn is_emoji_only(config: &Config, message: &str) -> bool {
+    let mut count = 0;
+    let mut rest = message.trim_start();
+    while !rest.is_empty() {
+        let emoji_len = match EMOJI_RE.captures(rest) {
+            Some(captures)
+                if captures.get(0).unwrap().start() == 0
+                    && lookup_emoji(config, &captures).is_some() =>
+            {
+                captures.get(0).unwrap().end()
+            }
+            // Unicode emoji can be made of several characters (ex: flags, skin tones, and
+            // families), so try to match the longest emoji possible.
+            _ => match rest
+                .char_indices()
+                .skip(1)
+                .map(|(i, _)| i)
+                .chain(std::iter::once(rest.len()))
+                .take(12)
+                .filter(|&i| emojis::get(&rest[..i]).is_some())
+                .last()
+            {
+                Some(emoji_len) => emoji_len,
+                None => return false,
+            },
+        };
+        count += 1;
+        rest = rest[emoji_len..].trim_start();
+    }
+    count > 0 && count <= MAX_JUMBO_EMOJI
+}
+
+// Syntax highlight the contents of any fenced code block whose language is known to syntect.
+// Cohost strips `class` attributes, so the colors are written as inline `style` attributes on
+// spans instead. Code blocks in unknown languages (or without a language) are left as-is.
+fn highlight_code_blocks<'a>(
+    events: Vec<Event<'a>>,
+    theme: &Theme,
+) -> tera::Result<Vec<Event<'a>>> {
+    let mut output = vec![];
+    // The highlighter and the code seen so far for the code block that is currently open, if any.
+    let mut code_block: Option<(HighlightLines, String)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref info))) => {
+                let lang = info.split(' ').next().unwrap_or("");
+                if let Some(syntax) = SYNTAX_SET.find_syntax_by_token(lang) {
+                    code_block = Some((HighlightLines::new(syntax, theme), String::new()));
+                }
+                output.push(event);
+            }
+            Event::Text(text) if code_block.is_some() => {
+                if let Some((_, code)) = &mut code_block {
+                    code.push_str(&text);
+                }
+            }
+            Event::End(Tag::CodeBlock(_)) => {
+                if let Some((mut highlighter, code)) = code_block.take() {
+                    let mut html = String::new();
+                    for line in LinesWithEndings::from(&code) {
+                        let regions =
+                            highlighter
+                                .highlight_line(line, &SYNTAX_SET)
+                                .map_err(|err| {
+                                    tera::Error::chain("couldn't highlight code block", err)
+                                })?;
+                        let line = styled_line_to_highlighted_html(&regions, IncludeBackground::No)
+                            .map_err(|err| {
+                                tera::Error::chain("couldn't highlight code block", err)
+                            })?;
+                        html.push_str(&line);
+                    }
+                    output.push(Event::Html(html.into()));
+                }
+                output.push(event);
+            }
+            _ => output.push(event),
+        }
+    }
+
+    Ok(output)
+}
+
+// Rewrite the events whose default HTML either looks bad or doesn't work at all on Cohost. This
+// works on the events themselves (instead of on the HTML text), so raw HTML written by the user is
+// passed through untouched, aside from <u> tags.
+fn convert_cohost_tags<'a>(events: Vec<Event<'a>>, options: &MarkdownOptions) -> Vec<Event<'a>> {
+    let mut output = vec![];
+    // Footnotes are numbered in the order they first appear in, like pulldown_cmark does.
+    let mut footnote_numbers: HashMap<String, usize> = HashMap::new();
+    let mut footnote_number = |name: &str| {
+        let next_number = footnote_numbers.len() + 1;
+        *footnote_numbers
+            .entry(name.to_string())
+            .or_insert(next_number)
+    };
+
+    for event in events {
+        let event = match event {
+            // pulldown-cmark adds <p> tags to the HTMLified text, which is undesirable since
+            // this also adds a large margin to all the text contained within it. Hence, we skip
+            // the <p> tags entirely and just keep the newline that would have followed them.
+            Event::Start(Tag::Paragraph) => continue,
+            Event::End(Tag::Paragraph) => Event::Html("\n".into()),
+            // pulldown_cmark encodes code blocks like this:
+            //  ```rust
+            //  my code block
+            //  ```
+            // as
+            // `<pre><code class=language-rust>my code block</code></pre>
+            // We will transform this into `<div class="cohoard-codeblock language-rust>my code block</div>`
+            // Note that if the language specifier is left off (ex: `rust` is left off in the example above)
+            // then we don't add the `language-rust` class.
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let lang = match &kind {
+                    CodeBlockKind::Fenced(info) => info.split(' ').next().unwrap_or(""),
+                    CodeBlockKind::Indented => "",
+                };
+                let html = if lang.is_empty() {
+                    r#"<div class="cohoard-codeblock">"#.to_string()
+                } else {
+                    let mut html = r#"<div class="cohoard-codeblock language-"#.to_string();
+                    escape_html(&mut html, lang).unwrap();
+                    html.push_str(r#"">"#);
+                    html
+                };
+                Event::Html(html.into())
+            }
+            Event::End(Tag::CodeBlock(_)) => Event::Html("</div>\n".into()),
+            // Cohost attaches pseudo-elements to <code>, which can't be overriden with inline styling
+            // to get around this, we replace it with a span that we can manually style in a template.
+            Event::Code(code) => {
+                let mut html = r#"<span class="cohoard-code">"#.to_string();
+                escape_html(&mut html, &code).unwrap();
+                html.push_str("</span>");
+                Event::Html(html.into())
+            }
+            // Cohost doesn't accept <u>, so we need to replace it with a span that can be manually
+            // styled. Only tags which the user wrote on their own are replaced--any other raw HTML,
+            // even if it happens to contain a <u> tag somewhere inside of it, is left alone.
+            Event::Html(html) => match html.trim().to_ascii_lowercase().as_str() {
+                "<u>" => Event::Html(UNDERLINE_START.into()),
+                "</u>" => Event::Html("</span>".into()),
+                _ => Event::Html(html),
+            },
+            // If headings are disabled, write them out as plain text instead.
+            Event::Start(Tag::Heading(level, _, _)) if !options.headings => {
+                Event::Text(format!("{} ", "#".repeat(level as usize)).into())
+            }
+            Event::End(Tag::Heading(..)) if !options.headings => Event::Html("\n".into()),
+            // Cohost styles blockquotes with a big italic quote mark, which doesn't look like a
+            // Discord quote at all. Use a div that templates can style instead.
+            Event::Start(Tag::BlockQuote) if options.discord_quotes => {
+                Event::Html(r#"<div class="cohoard-blockquote">"#.into())
+            }
+            Event::End(Tag::BlockQuote) if options.discord_quotes => Event::Html("</div>\n".into()),
+            // Task list checkboxes are <input> tags, which Cohost strips. Use a character instead.
+            Event::TaskListMarker(checked) => Event::Html(
+                format!(
+                    r#"<span class="cohoard-tasklist-marker">{}</span> "#,
+                    if checked { "\u{2611}" } else { "\u{2610}" }
+                )
+                .into(),
+            ),
+            // Cohost strips `id` attributes, so footnote links wouldn't go anywhere. Just show the
+            // number of the footnote instead.
+            Event::FootnoteReference(name) => Event::Html(
+                format!(
+                    r#"<sup class="cohoard-footnote-reference">[{}]</sup>"#,
+                    footnote_number(&name)
+                )
+                .into(),
+            ),
+            Event::Start(Tag::FootnoteDefinition(name)) => Event::Html(
+                format!(
+                    r#"<div class="cohoard-footnote"><sup class="cohoard-footnote-label">{}</sup> "#,
+                    footnote_number(&name)
+                )
+                .into(),
+            ),
+            Event::End(Tag::FootnoteDefinition(_)) => Event::Html("</div>\n".into()),
+            _ => event,
+        };
+        output.push(event);
+    }
+    output
+}
+
+// Convert `__underscore__` text into underlined text, like Discord does. pulldown_cmark only
+// understands `__underscore__` as `**bold**`, so we look back at the source text to find out which
+// Strong tags were written with underscores and turn those into underline spans instead.
+fn convert_underlines<'a>(
+    source: &'a str,
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+) -> impl Iterator<Item = Event<'a>> {
+    // Whether each currently open Strong tag was written with underscores.
+    let mut underlines = vec![];
+    events.map(move |(event, range)| match event {
+        Event::Start(Tag::Strong) => {
+            let is_underline = source[range].starts_with("__");
+            underlines.push(is_underline);
+            if is_underline {
+                Event::Html(UNDERLINE_START.into())
+            } else {
+                event
+            }
+        }
+        Event::End(Tag::Strong) => {
+            if underlines.pop().unwrap_or(false) {
+                Event::Html("</span>".into())
+            } else {
+                event
+            }
+        }
+        _ => event,
+    })
+}
+
+// Convert Discord-style `||spoiler||` text into a click-to-reveal `<details>` element.
+// pulldown_cmark doesn't know about spoilers, so the `||` delimiters show up inside of Text events.
+// We split the Text events on the delimiters and pair them up, so long as both delimiters are
+// inside the same element (ex: `||**bold**||` is fine, but `**bold||** text||` is left alone).
+// Unpaired delimiters are left as literal text, and code blocks are left alone.
+fn convert_spoilers<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    const SPOILER_START: &str = r#"<details class="cohoard-spoiler"><summary class="cohoard-spoiler-cover">spoiler</summary><span class="cohoard-spoiler-content">"#;
+    const SPOILER_END: &str = "</span></details>";
+
+    let mut output: Vec<Event> = vec![];
+    // The index into `output` and the nesting depth of the currently unpaired opening delimiter.
+    let mut open_delimiter: Option<(usize, usize)> = None;
+    let mut depth = 0;
+    let mut in_code_block = false;
+
+    for event in events {
+        match event {
+            Event::Text(text) if !in_code_block => {
+                // pulldown_cmark sometimes splits a run of text across several Text events, which
+                // could split a delimiter in half. Hence, merge with the previous Text, if any.
+                let text = match output.last() {
+                    Some(Event::Text(prev))
+                        if open_delimiter.map(|(i, _)| i) != Some(output.len() - 1) =>
+                    {
+                        let merged = format!("{}{}", prev, text);
+                        output.pop();
+                        merged
+                    }
+                    _ => text.to_string(),
+                };
+
+                let mut pieces = text.split("||");
+                if let Some(first) = pieces.next() {
+                    if !first.is_empty() {
+                        output.push(Event::Text(first.to_string().into()));
+                    }
+                }
+                for piece in pieces {
+                    match open_delimiter {
+                        Some((index, open_depth)) if open_depth == depth => {
+                            output[index] = Event::Html(SPOILER_START.into());
+                            output.push(Event::Html(SPOILER_END.into()));
+                            open_delimiter = None;
+                        }
+                        Some(_) => output.push(Event::Text("||".into())),
+                        None => {
+                            open_delimiter = Some((output.len(), depth));
+                            output.push(Event::Text("||".into()));
+                        }
+                    }
+                    if !piece.is_empty() {
+                        output.push(Event::Text(piece.to_string().into()));
+                    }
+                }
+            }
+            Event::Start(ref tag) => {
+                if matches!(tag, Tag::CodeBlock(_)) {
+                    in_code_block = true;
+                }
+                depth += 1;
+                output.push(event);
+            }
+            Event::End(ref tag) => {
+                if matches!(tag, Tag::CodeBlock(_)) {
+                    in_code_block = false;
+                }
+                depth -= 1;
+                // A spoiler can't extend past the end of the element it was opened in.
+                if matches!(open_delimiter, Some((_, open_depth)) if open_depth > depth) {
+                    open_delimiter = None;
+                }
+                output.push(event);
+            }
+            _ => output.push(event),
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+fn render_markdown(message: &str, args: &HashMap<String, tera::Value>) -> String {
+    let value = markdown_to_html(&Config::default(), &tera::Value::from(message), args).unwrap();
+    value.as_str().unwrap().trim().to_string()
+}
+
+#[test]
+fn test_spoiler() {
+    let markdown = |message: &str| render_markdown(message, &HashMap::new());
+
+    let spoiler = |content: &str| {
+        format!(
+            r#"<details class="cohoard-spoiler"><summary class="cohoard-spoiler-cover">spoiler</summary><span class="cohoard-spoiler-content">{}</span></details>"#,
+            content
+        )
+    };
+
+    assert_eq!(
+        markdown("the cake is ||a lie||!"),
+        format!("the cake is {}!", spoiler("a lie"))
+    );
+    assert_eq!(
+        markdown("||**bold**|| and ||two||"),
+        format!(
+            "{} and {}",
+            spoiler("<strong>bold</strong>"),
+            spoiler("two")
+        )
+    );
+    // Delimiters inside of code spans aren't spoilers.
+    assert_eq!(
+        markdown("`a || b || c`"),
+        r#"<span class="cohoard-code">a || b || c</span>"#
+    );
+    // ... and neither are delimiters inside of code blocks.
+    assert_eq!(
+        markdown("```\na || b || c\n```"),
+        "<div class=\"cohoard-codeblock\">a || b || c\n</div>"
+    );
+    assert_eq!(
+        markdown("    a || b || c"),
+        "<div class=\"cohoard-codeblock\">a || b || c</div>"
+    );
+    // Unpaired delimiters and delimiters crossing element boundaries are left alone.
+    assert_eq!(markdown("a || b"), "a || b");
+    assert_eq!(markdown("**a||** b||"), "<strong>a||</strong> b||");
+}
+
+#[test]
+fn test_underline() {
+    let markdown = |message: &str, dialect: &str| {
+        render_markdown(message, &[("dialect".to_string(), dialect.into())].into())
+    };
+
+    assert_eq!(
+        markdown("__under__ and **bold**", "discord"),
+        r#"<span class="cohoard-underline">under</span> and <strong>bold</strong>"#
+    );
+    assert_eq!(
+        markdown("***__both__***", "discord"),
+        r#"<em><strong><span class="cohoard-underline">both</span></strong></em>"#
+    );
+    assert_eq!(
+        markdown("__under__ and **bold**", "commonmark"),
+        "<strong>under</strong> and <strong>bold</strong>"
+    );
+    // Underscores inside of code spans are untouched.
+    assert_eq!(
+        markdown("`__init__`", "discord"),
+        r#"<span class="cohoard-code">__init__</span>"#
+    );
+}
+
+#[test]
+fn test_cohost_tags() {
+    let markdown = |message: &str| render_markdown(message, &HashMap::new());
+
+    assert_eq!(markdown("first\n\nsecond"), "first\nsecond");
+    assert_eq!(
+        markdown("`<code>`"),
+        r#"<span class="cohoard-code">&lt;code&gt;</span>"#
+    );
+    assert_eq!(
+        markdown("```rust\nfn main() {}\n```"),
+        r#"<div class="cohoard-codeblock language-rust">fn main() {}
+</div>"#
+    );
+    assert_eq!(
+        markdown("```\n<pre><code>\n```"),
+        r#"<div class="cohoard-codeblock">&lt;pre&gt;&lt;code&gt;
+</div>"#
+    );
+    assert_eq!(
+        markdown("```a\"b\nx\n```"),
+        r#"<div class="cohoard-codeblock language-a&quot;b">x
+</div>"#
+    );
+    assert_eq!(
+        markdown("<u>underline</u> and <U>UNDERLINE</U>"),
+        r#"<span class="cohoard-underline">underline</span> and <span class="cohoard-underline">UNDERLINE</span>"#
+    );
+}
+
+#[test]
+fn test_raw_html_untouched() {
+    let markdown = |message: &str| render_markdown(message, &HashMap::new());
+
+    // Raw HTML written by the user shouldn't be mangled, even if it looks like the HTML that
+    // pulldown_cmark outputs.
+    assert_eq!(markdown("a <p>b</p> c"), "a <p>b</p> c");
+    assert_eq!(markdown("a <code>b</code> c"), "a <code>b</code> c");
+    assert_eq!(
+        markdown(r#"a <pre><code class="language-rust">b</code></pre>"#),
+        r#"a <pre><code class="language-rust">b</code></pre>"#
+    );
+    assert_eq!(
+        markdown(r#"<div class="u"><u>x</u></div>"#),
+        r#"<div class="u"><u>x</u></div>"#
+    );
+}
+
+#[test]
+fn test_highlighting() {
+    let markdown = |message: &str, theme: &str| {
+        render_markdown(message, &[("code_theme".to_string(), theme.into())].into())
+    };
+
+    let html = markdown("```rust\nfn main() {}\n```", "InspiredGitHub");
+    assert_eq!(
+        html,
+        "<div class=\"cohoard-codeblock language-rust\">\
+            <span style=\"font-weight:bold;color:#a71d5d;\">fn </span>\
+            <span style=\"font-weight:bold;color:#795da3;\">main</span>\
+            <span style=\"color:#323232;\">() {}\n</span>\
+        </div>"
+    );
+
+    // Text in the code block is still escaped.
+    let html = markdown("```html\n<p>&amp;</p>\n```", "InspiredGitHub");
+    assert!(html.contains("&lt;"));
+    assert!(!html.contains("<p>"));
+
+    // Unknown (or missing) languages aren't highlighted.
+    assert_eq!(
+        markdown("```notalanguage\nfn main() {}\n```", "InspiredGitHub"),
+        "<div class=\"cohoard-codeblock language-notalanguage\">fn main() {}\n</div>"
+    );
+    assert_eq!(
+        markdown("```\nfn main() {}\n```", "InspiredGitHub"),
+        "<div class=\"cohoard-codeblock\">fn main() {}\n</div>"
+    );
+
+    // Unknown themes are an error.
+    let args = [("code_theme".to_string(), "not a theme".into())].into();
+    assert!(markdown_to_html(&Config::default(), &tera::Value::from("`code`"), &args).is_err());
+}
+
+#[test]
+fn test_markdown_options() {
+    let markdown = |message: &str, args: &[(&str, tera::Value)]| {
+        let args = args
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.clone()))
+            .collect();
+        render_markdown(message, &args)
+    };
+
+    assert_eq!(
+        markdown("- [x] done\n- [ ] not done", &[("tasklists", true.into())]),
+        "<ul>\n<li><span class=\"cohoard-tasklist-marker\">\u{2611}</span> done</li>\n\
+         <li><span class=\"cohoard-tasklist-marker\">\u{2610}</span> not done</li>\n</ul>"
+    );
+    assert_eq!(
+        markdown(
+            "a[^note] b[^other]\n\n[^note]: c",
+            &[("footnotes", true.into())]
+        ),
+        "a<sup class=\"cohoard-footnote-reference\">[1]</sup> \
+         b<sup class=\"cohoard-footnote-reference\">[2]</sup>\n\
+         <div class=\"cohoard-footnote\"><sup class=\"cohoard-footnote-label\">1</sup> c\n</div>"
+    );
+    assert!(markdown("a | b\n--|--\nc | d", &[("tables", true.into())]).starts_with("<table>"));
+    assert_eq!(markdown("a | b\n--|--\nc | d", &[]), "a | b\n--|--\nc | d");
+
+    assert_eq!(markdown("## heading", &[]), "<h2>heading</h2>");
+    assert_eq!(
+        markdown("## heading", &[("headings", false.into())]),
+        "## heading"
+    );
+
+    // Discord quotes only last for a single line, unless they're `>>>` quotes.
+    let discord = || ("dialect", tera::Value::from("discord"));
+    assert_eq!(
+        markdown("> quote\nnot quote", &[discord()]),
+        "<div class=\"cohoard-blockquote\">quote\n</div>\nnot quote"
+    );
+    assert_eq!(
+        markdown(">>> quote\nstill quote", &[discord()]),
+        "<div class=\"cohoard-blockquote\">quote\nstill quote\n</div>"
+    );
+    assert_eq!(markdown(">not quote", &[discord()]), "&gt;not quote");
+    assert_eq!(
+        markdown("> quote\nlazy", &[]),
+        "<blockquote>\nquote\nlazy\n</blockquote>"
+    );
+
+    assert_eq!(
+        markdown("-# small\nbig", &[discord()]),
+        "<span class=\"cohoard-subtext\">small</span>\nbig"
+    );
+    assert_eq!(
+        markdown("-# small", &[discord(), ("subtext", false.into())]),
+        "-# small"
+    );
+
+    let args = [("not_an_option".to_string(), true.into())].into();
+    assert!(markdown_to_html(&Config::default(), &tera::Value::from("text"), &args).is_err());
+}
+
+#[test]
+fn test_mentions() {
+    let config = crate::config::load_config(
+        r##"people:
+    - key: KARKAT
+      name: Karkat <Vantas>
+    - key: JUICE
+  "##,
+    )
+    .unwrap();
+    let markdown = |message: &str| {
+        let args = [("dialect".to_string(), "discord".into())].into();
+        let value = markdown_to_html(&config, &tera::Value::from(message), &args).unwrap();
+        value.as_str().unwrap().trim().to_string()
+    };
+
+    assert_eq!(
+        markdown("hi @KARKAT and <@JUICE>!"),
+        "hi <span class=\"cohoard-mention cohoard-mention-KARKAT\">@Karkat &lt;Vantas&gt;</span> \
+         and <span class=\"cohoard-mention cohoard-mention-JUICE\">@JUICE</span>!"
+    );
+    // Unknown users, email addresses, code, and unexpanded at-macros aren't mentions.
+    assert_eq!(markdown("hi @NOBODY"), "hi @NOBODY");
+    assert_eq!(markdown("me@KARKAT.com"), "me@KARKAT.com");
+    assert_eq!(markdown("@KARKAT.nofield"), "@KARKAT.nofield");
+    assert_eq!(
+        markdown("`@KARKAT`"),
+        "<span class=\"cohoard-code\">@KARKAT</span>"
+    );
+
+    assert_eq!(
+        markdown("```\n@KARKAT\n```"),
+        "<div class=\"cohoard-codeblock\">@KARKAT\n</div>"
+    );
+
+    let mentions = find_mentions(&config, "@JUICE `@KARKAT` <@JUICE>\n```\n@KARKAT\n```");
+    assert_eq!(mentions.len(), 1);
+    let mentions = find_mentions(&config, "@JUICE @KARKAT `@NOBODY` <@JUICE>");
+    let mentions: Vec<_> = mentions
+        .iter()
+        .map(|user| user.get_str("key").unwrap())
+        .collect();
+    assert_eq!(mentions, ["JUICE", "KARKAT"]);
+}
+
+#[test]
+fn test_emoji() {
+    let config = crate::config::load_config(
+        r##"people: []
+emoji:
+  eggbug: https://example.com/eggbug.png
+  smile: https://example.com/not-a-smile.png
+  "##,
+    )
+    .unwrap();
+    let markdown = |message: &str| {
+        let value =
+            markdown_to_html(&config, &tera::Value::from(message), &HashMap::new()).unwrap();
+        value.as_str().unwrap().trim().to_string()
+    };
+    let image = |name: &str, url: &str| {
+        format!(
+            r#"<img class="cohoard-emoji" src="{}" alt=":{}:" title=":{}:" style="display: inline; height: 1.375em; width: auto; margin: 0; vertical-align: bottom;">"#,
+            url, name, name
+        )
+    };
+
+    assert_eq!(
+        markdown("hello :eggbug: :wave:!"),
+        format!(
+            "hello {} \u{1F44B}!",
+            image("eggbug", "https://example.com/eggbug.png")
+        )
+    );
+    // Custom emoji take priority over Unicode emoji.
+    assert_eq!(
+        markdown("a :smile:"),
+        format!(
+            "a {}",
+            image("smile", "https://example.com/not-a-smile.png")
+        )
+    );
+    assert_eq!(
+        markdown("a <:blobcat:1234> <a:party:5678>"),
+        format!(
+            "a {} {}",
+            image("blobcat", "https://cdn.discordapp.com/emojis/1234.png"),
+            image("party", "https://cdn.discordapp.com/emojis/5678.gif")
+        )
+    );
+    // Unknown shortcodes and code are left alone.
+    assert_eq!(
+        markdown("at 10:30:00 :notanemoji:"),
+        "at 10:30:00 :notanemoji:"
+    );
+    assert_eq!(
+        markdown("`:wave:`"),
+        r#"<span class="cohoard-code">:wave:</span>"#
+    );
+    assert_eq!(
+        markdown("```\n:wave: <:blobcat:1234>\n```"),
+        "<div class=\"cohoard-codeblock\">:wave: &lt;:blobcat:1234&gt;\n</div>"
+    );
+
+    // Messages with only emoji have bigger emoji.
+    let jumbo = |html: &str| {
+        format!(
+            r#"<span class="cohoard-emoji-jumbo" style="font-size: 2.2em; line-height: 1.2;">{}</span>"#,
+            html
+        )
+    };
+    assert_eq!(
+        markdown(":wave: \u{1F1FA}\u{1F1F8} \u{1F44D}\u{1F3FD}"),
+        jumbo("\u{1F44B} \u{1F1FA}\u{1F1F8} \u{1F44D}\u{1F3FD}")
+    );
+    assert_eq!(
+        markdown(":eggbug:"),
+        jumbo(&image("eggbug", "https://example.com/eggbug.png"))
+    );
+    assert_eq!(markdown(":wave: hi"), "\u{1F44B} hi");
+    assert_eq!(markdown(&":wave:".repeat(28)), "\u{1F44B}".repeat(28));
+
+    let args = [("emoji".to_string(), false.into())].into();
+    let value = markdown_to_html(&config, &tera::Value::from(":wave:"), &args).unwrap();
+    assert_eq!(value.as_str().unwrap().trim(), ":wave:");
+}
+
+#[test]
+fn test_autolinks() {
+    let markdown = |message: &str| render_markdown(message, &HashMap::new());
+
+    assert_eq!(
+        markdown("see https://cohost.org/rc/welcome."),
+        r#"see <a href="https://cohost.org/rc/welcome">https://cohost.org/rc/welcome</a>."#
+    );
+    assert_eq!(
+        markdown("(https://en.wikipedia.org/wiki/Cohost_(website))"),
+        r#"(<a href="https://en.wikipedia.org/wiki/Cohost_(website)">https://en.wikipedia.org/wiki/Cohost_(website)</a>)"#
+    );
+    assert_eq!(
+        markdown("(see http://example.com/a&b)"),
+        r#"(see <a href="http://example.com/a&amp;b">http://example.com/a&amp;b</a>)"#
+    );
+    // URLs which are already links, or are in code, are left alone.
+    assert_eq!(
+        markdown("[https://a.com](https://b.com) <https://c.com>"),
+        r#"<a href="https://b.com">https://a.com</a> <a href="https://c.com">https://c.com</a>"#
+    );
+    assert_eq!(
+        markdown("`https://a.com`"),
+        r#"<span class="cohoard-code">https://a.com</span>"#
+    );
+    assert_eq!(
+        markdown("```\nhttps://a.com\n```"),
+        "<div class=\"cohoard-codeblock\">https://a.com\n</div>"
+    );
+    assert_eq!(markdown("just https:// alone"), "just https:// alone");
+
+    let args = [("autolinks".to_string(), false.into())].into();
+    assert_eq!(render_markdown("https://a.com", &args), "https://a.com");
+}
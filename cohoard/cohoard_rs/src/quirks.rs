@@ -0,0 +1,148 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::config::{Quirk, QuirkCase};
+
+/// Messages starting with this are typed as-is, without the poster's quirk.
+const NO_QUIRK: &str = "!noquirk";
+
+lazy_static! {
+    // Matches the parts of a message that quirks shouldn't change, since changing them would break
+    // their meaning: inline code, at-macros and mentions, URLs, emoji shortcodes, and tags (which
+    // includes Discord's `<@KEY>` mentions and `<:name:id>` emoji).
+    static ref PROTECTED_RE: Regex = Regex::new(
+        r"`[^`]*`|\\?@[\p{Alphabetic}\p{N}]+(?:\.\w+(?:\|\w+)*)?|https?://\S+|:[\w+-]+:|<[@:/a-zA-Z][^<>]*>"
+    )
+    .unwrap();
+}
+
+/// Apply a poster's quirk to one of their messages. See [`Quirk`].
+///
+/// Code blocks, inline code, at-macros, mentions, URLs, emoji, and tags are left alone. Messages
+/// starting with `!noquirk` are also left alone, and have the `!noquirk` removed. This is done even
+/// if `quirk` is None.
+pub(crate) fn apply_quirk(quirk: Option<&Quirk>, message: &str) -> String {
+    if let Some(message) = message.strip_prefix(NO_QUIRK) {
+        return message.strip_prefix(' ').unwrap_or(message).to_string();
+    }
+    let Some(quirk) = quirk else {
+        return message.to_string();
+    };
+
+    // Whether the next letter should be uppercase for `QuirkCase::Alternating`. This carries on
+    // across the protected parts of the message.
+    let mut next_upper = true;
+    let mut transform = |text: &str| {
+        let mut text = match quirk.case {
+            QuirkCase::None => text.to_string(),
+            QuirkCase::Upper => text.to_uppercase(),
+            QuirkCase::Lower => text.to_lowercase(),
+            QuirkCase::Alternating => text
+                .chars()
+                .flat_map(|c| {
+                    let upper = next_upper;
+                    if c.is_alphabetic() {
+                        next_upper = !next_upper;
+                    }
+                    let cased: Vec<char> = if upper {
+                        c.to_uppercase().collect()
+                    } else {
+                        c.to_lowercase().collect()
+                    };
+                    cased
+                })
+                .collect(),
+        };
+        for replacement in &quirk.replace {
+            text = replacement
+                .from
+                .replace_all(&text, replacement.to.as_str())
+                .into_owned();
+        }
+        text
+    };
+
+    // The lines of the message, along with whether each one is a line of text (as opposed to part
+    // of a code block).
+    let mut lines = vec![];
+    let mut in_code_block = false;
+    for line in message.split_inclusive('\n') {
+        if line.trim_start().starts_with("```") {
+            in_code_block = !in_code_block;
+            lines.push((line.to_string(), false));
+            continue;
+        }
+        if in_code_block {
+            lines.push((line.to_string(), false));
+            continue;
+        }
+
+        let mut new_line = String::new();
+        let mut last_end = 0;
+        for protected in PROTECTED_RE.find_iter(line) {
+            new_line.push_str(&transform(&line[last_end..protected.start()]));
+            new_line.push_str(protected.as_str());
+            last_end = protected.end();
+        }
+        new_line.push_str(&transform(&line[last_end..]));
+        lines.push((new_line, true));
+    }
+
+    // The prefix and suffix go on the first and last lines of text, so that they don't break any
+    // code blocks at the start or end of the message.
+    if let Some((first, _)) = lines.iter_mut().find(|(_, is_text)| *is_text) {
+        first.insert_str(0, &quirk.prefix);
+    }
+    if let Some((last, _)) = lines.iter_mut().rev().find(|(_, is_text)| *is_text) {
+        let content_len = last.trim_end_matches(['\r', '\n']).len();
+        last.insert_str(content_len, &quirk.suffix);
+    }
+
+    lines.into_iter().map(|(line, _)| line).collect()
+}
+
+#[test]
+fn test_quirks() {
+    use crate::config::QuirkReplacement;
+
+    let terezi = Quirk {
+        case: QuirkCase::Upper,
+        replace: vec![
+            QuirkReplacement {
+                from: Regex::new("A").unwrap(),
+                to: "4".to_string(),
+            },
+            QuirkReplacement {
+                from: Regex::new("(I|E)").unwrap(),
+                to: "$1$1".to_string(),
+            },
+        ],
+        prefix: ":] ".to_string(),
+        suffix: " >:]".to_string(),
+    };
+    assert_eq!(
+        apply_quirk(
+            Some(&terezi),
+            "hi @KARKAT.name, look at `code` and https://cohost.org\n"
+        ),
+        ":] HII @KARKAT.name, LOOK 4T `code` 4ND https://cohost.org >:]\n"
+    );
+    assert_eq!(
+        apply_quirk(
+            Some(&terezi),
+            "```\ncode block\n```\nhi\nthere\n```\ncode\n```\n"
+        ),
+        "```\ncode block\n```\n:] HII\nTHEEREE >:]\n```\ncode\n```\n"
+    );
+    assert_eq!(
+        apply_quirk(Some(&terezi), "!noquirk typed normally\n"),
+        "typed normally\n"
+    );
+
+    let gamzee = Quirk {
+        case: QuirkCase::Alternating,
+        ..Quirk::default()
+    };
+    assert_eq!(apply_quirk(Some(&gamzee), "honk honk :o)"), "HoNk HoNk :O)");
+    assert_eq!(apply_quirk(None, "!noquirk hi"), "hi");
+}
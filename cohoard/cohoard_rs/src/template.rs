@@ -0,0 +1,529 @@
+use std::{error::Error, fmt};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use tera::{Context, Tera};
+
+use crate::{
+    color::{normalize_color, Color},
+    config::Config,
+};
+
+lazy_static! {
+    // Matches a `{#-config ... config-#}` block in a template.
+    static ref CONTROLS_BLOCK_RE: Regex = Regex::new(r"(?s)\{#-config(?P<controls>.+?)config-#\}").unwrap();
+    // Matches a `{#-people ... people-#}` block in a template.
+    static ref PEOPLE_BLOCK_RE: Regex = Regex::new(r"(?s)\{#-people(?P<schema>.*?)people-#\}").unwrap();
+    // Matches a `{#-auto-colors ... auto-colors-#}` block in a template.
+    static ref AUTO_COLORS_BLOCK_RE: Regex =
+        Regex::new(r"(?s)\{#-auto-colors(?P<background>.*?)auto-colors-#\}").unwrap();
+}
+
+/// The kind of input that the web UI shows for a template control. These are the same as the
+/// `type`s of HTML `<input>` elements.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ControlKind {
+    /// A text box. This is the default.
+    #[default]
+    Text,
+    /// A checkbox, whose value is true or false.
+    Checkbox,
+    /// A color picker.
+    Color,
+    Url,
+    Number,
+    Range,
+    Email,
+    Date,
+    Time,
+    Datetime,
+    Radio,
+    File,
+    /// No input at all, which is used to show the label by itself.
+    Hidden,
+    /// Any other type of input, which is treated like a text box.
+    #[serde(other)]
+    Other,
+}
+
+/// A setting which a template shows in the web UI, and which is passed to the template as a
+/// variable. See [`parse_controls`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Control {
+    /// The name of the variable that the control sets, like `light_mode`.
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: ControlKind,
+    /// The label shown next to the control. This may contain HTML.
+    #[serde(default)]
+    pub label: String,
+    /// The initial value of the control. For checkboxes, this is checked if it is "true".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+    /// The placeholder text shown in the control when it is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<String>,
+}
+
+impl Control {
+    /// The value of the variable when the control hasn't been changed. This is a boolean for
+    /// checkboxes and a string for everything else, which is empty if there isn't a default. This
+    /// matches what the web UI passes to the template.
+    pub fn default_value(&self) -> Value {
+        let default = match &self.default {
+            None | Some(Value::Null) => None,
+            Some(Value::String(string)) => Some(string.clone()),
+            Some(value) => Some(value.to_string()),
+        };
+        match self.kind {
+            ControlKind::Checkbox => Value::Bool(default.as_deref() == Some("true")),
+            _ => Value::String(default.unwrap_or_default()),
+        }
+    }
+
+    /// Check that a value can be used for this control, returning the value that the template
+    /// gets. Checkboxes need true or false. Everything else needs text, but numbers and booleans are
+    /// also allowed, and are turned into text like the web UI would.
+    pub fn check_value(&self, value: Value) -> Result<Value, String> {
+        match (self.kind, value) {
+            (ControlKind::Checkbox, value @ Value::Bool(_)) => Ok(value),
+            (ControlKind::Checkbox, value) => Err(format!(
+                "{} is a checkbox, so it should be true or false, but is {}",
+                self.name, value
+            )),
+            (_, value @ Value::String(_)) => Ok(value),
+            (_, value @ (Value::Number(_) | Value::Bool(_))) => Ok(value.to_string().into()),
+            (_, value) => Err(format!("{} should be text, but is {}", self.name, value)),
+        }
+    }
+}
+
+/// Get the controls of a template, which are the settings that the web UI shows for it.
+///
+/// The controls are declared by a comment which begins with "-config" and ends with "config-".
+/// Inside of it is a JSON array of objects, each of which have a `name`, an optional `type` (see
+/// [`ControlKind`], defaulting to "text"), an optional `label`, an optional `default`, and an
+/// optional `placeholder`. For example:
+/// ```no_compile
+/// {#-config
+///    [{ "name": "light_mode", "type": "checkbox", "label": "Use Light Mode" },
+///     { "name": "avatar_color", "type": "color", "label": "Avatar Color", "default": "#43b581" }]
+/// config-#}
+/// ```
+/// A template can have more than one of these comments, in which case the controls from all of
+/// them are returned, in order. Templates without any controls return an empty list.
+pub fn parse_controls(template: &str) -> Result<Vec<Control>, Box<dyn Error>> {
+    let mut controls = vec![];
+    for captures in CONTROLS_BLOCK_RE.captures_iter(template) {
+        let block: Vec<Control> = serde_json::from_str(&captures["controls"])
+            .map_err(|err| format!("Couldn't parse the template's config block: {}", err))?;
+        controls.extend(block);
+    }
+    Ok(controls)
+}
+
+/// The type of a field in a template's person schema.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// Any text. This is the default.
+    #[default]
+    String,
+    /// A CSS color, such as `#ffea02`, `rgb(255, 234, 2)`, or `yellow`.
+    Color,
+    /// An http, https, or data URL.
+    Url,
+    Number,
+    Boolean,
+}
+
+/// A field that a template uses from each person in the config. See [`parse_person_schema`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersonField {
+    /// The name of the field, like `avatar`.
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub field_type: FieldType,
+    /// If true, every person in the config must have this field.
+    #[serde(default)]
+    pub required: bool,
+    /// A user-facing description of the field.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// A problem with a field of a person in the config, found by [`validate_config`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ValidationProblem {
+    /// The key of the person with the problem.
+    pub person: String,
+    /// The name of the field with the problem.
+    pub field: String,
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for ValidationProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.person, self.field, self.message)
+    }
+}
+
+/// Get the person schema of a template, which lists the fields that the template uses from each
+/// person in the config.
+///
+/// The schema is declared by a comment which begins with "-people" and ends with "people-". Inside
+/// of it is a JSON array of objects, each of which have a `name`, an optional `type` (one of
+/// "string", "color", "url", "number", or "boolean", defaulting to "string"), an optional
+/// `required` flag, and an optional `description`. For example:
+/// ```no_compile
+/// {#-people
+///    [{ "name": "handle", "required": true },
+///     { "name": "avatar", "type": "url", "required": true },
+///     { "name": "color", "type": "color" }]
+/// people-#}
+/// ```
+/// Templates without a person schema return an empty list.
+pub fn parse_person_schema(template: &str) -> Result<Vec<PersonField>, Box<dyn Error>> {
+    match PEOPLE_BLOCK_RE.captures(template) {
+        Some(captures) => serde_json::from_str(&captures["schema"])
+            .map_err(|err| format!("Couldn't parse the template's person schema: {}", err).into()),
+        None => Ok(vec![]),
+    }
+}
+
+/// Get the background color that a template wants automatic colors to be readable on, if it wants
+/// speakers who aren't in the config to get automatic colors. See [`Config::get_user`].
+///
+/// This is declared by a comment which begins with "-auto-colors" and ends with "auto-colors-".
+/// Inside of it is the background color, which is rendered as a template with the given context
+/// first, so that it can depend on the template's settings. For example:
+/// ```no_compile
+/// {#-auto-colors {% if light_mode %} #ffffff {% else %} #36393e {% endif %} auto-colors-#}
+/// ```
+pub(crate) fn parse_auto_colors(
+    template: &str,
+    context: &Context,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let Some(captures) = AUTO_COLORS_BLOCK_RE.captures(template) else {
+        return Ok(None);
+    };
+    let background = Tera::one_off(&captures["background"], context, false)?;
+    match Color::parse(&background) {
+        Some(color) => Ok(Some(color.to_hex())),
+        None => Err(format!(
+            "The template's auto-colors background \"{}\" isn't a hex, named, rgb(), or hsl() color",
+            background.trim()
+        )
+        .into()),
+    }
+}
+
+/// Check the people in a config against the person schema of a template (see
+/// [`parse_person_schema`]), returning every missing required field and every field whose value
+/// doesn't match the type in the schema. The problems are sorted by person, and then listed in the
+/// order of the schema.
+pub fn validate_config(
+    template: &str,
+    config: &Config,
+) -> Result<Vec<ValidationProblem>, Box<dyn Error>> {
+    let schema = parse_person_schema(template)?;
+
+    let mut keys: Vec<&String> = config.people.keys().collect();
+    keys.sort();
+
+    let mut problems = vec![];
+    for key in keys {
+        let user = &config.people[key];
+        for field in &schema {
+            let message = match user.fields.get(&field.name) {
+                None if field.required => "is required, but is missing".to_string(),
+                None => continue,
+                Some(value) => match check_type(value, field.field_type) {
+                    Ok(()) => continue,
+                    Err(message) => message,
+                },
+            };
+            problems.push(ValidationProblem {
+                person: key.clone(),
+                field: field.name.clone(),
+                message,
+            });
+        }
+    }
+    Ok(problems)
+}
+
+// Check that a value of a field has the given type, returning a description of the problem if it
+// doesn't.
+fn check_type(value: &Value, field_type: FieldType) -> Result<(), String> {
+    let valid = match (field_type, value) {
+        (FieldType::String, Value::String(_)) => true,
+        (FieldType::Color, Value::String(color)) => is_color(color),
+        (FieldType::Url, Value::String(url)) => {
+            ["http://", "https://", "data:"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+                && !url.contains(char::is_whitespace)
+        }
+        (FieldType::Number, Value::Number(_)) => true,
+        (FieldType::Boolean, Value::Bool(_)) => true,
+        _ => false,
+    };
+
+    if valid {
+        return Ok(());
+    }
+    let expected = match field_type {
+        FieldType::String => "text",
+        FieldType::Color => "a CSS color (like \"#ffea02\")",
+        FieldType::Url => "a URL starting with http://, https://, or data:",
+        FieldType::Number => "a number",
+        FieldType::Boolean => "true or false",
+    };
+    Err(format!("should be {}, but is {}", expected, value))
+}
+
+// Check if a string is a CSS color.
+fn is_color(color: &str) -> bool {
+    normalize_color(color).is_ok()
+}
+
+/// A template which is built into cohoard, such as the Discord template. These are the same
+/// templates that the web UI offers. See [`builtin_templates`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BuiltinTemplate {
+    /// The name used to pick the template, like `discord`. This is the name of the template's file,
+    /// without the extension.
+    pub name: &'static str,
+    /// The name shown for the template, like `Discord`.
+    pub title: &'static str,
+    /// A short description of what the template looks like.
+    pub description: &'static str,
+    /// The contents of the template.
+    #[serde(skip)]
+    pub source: &'static str,
+}
+
+// Declare a built-in template from a file in the `templates` directory.
+macro_rules! builtin_template {
+    ($name:literal, $title:literal, $description:literal) => {
+        BuiltinTemplate {
+            name: $name,
+            title: $title,
+            description: $description,
+            source: include_str!(concat!("../templates/", $name, ".html")),
+        }
+    };
+}
+
+static BUILTIN_TEMPLATES: &[BuiltinTemplate] = &[
+    builtin_template!(
+        "discord",
+        "Discord",
+        "A Discord chat, in light or dark mode."
+    ),
+    builtin_template!(
+        "twitter",
+        "Twitter",
+        "A Twitter timeline, with each message as a tweet."
+    ),
+    builtin_template!(
+        "17776",
+        "17776",
+        "The chat logs of the satellites from 17776."
+    ),
+    builtin_template!("homestuck", "MSPA", "A Pesterlog from Homestuck."),
+    builtin_template!(
+        "undertale",
+        "Undertale",
+        "Undertale dialogue boxes, with portraits."
+    ),
+    builtin_template!("wikipedia", "Wikipedia", "A Wikipedia article."),
+    builtin_template!("chatgpt", "ChatGPT", "A ChatGPT conversation."),
+    builtin_template!(
+        "corru_observer",
+        "corru.observer",
+        "Dialogue from corru.observer."
+    ),
+    builtin_template!(
+        "basic",
+        "Basic",
+        "A minimal template which explains how templates work."
+    ),
+];
+
+/// Get all of the built-in templates.
+pub fn builtin_templates() -> &'static [BuiltinTemplate] {
+    BUILTIN_TEMPLATES
+}
+
+/// Get the built-in template with the given name (like `discord`), if there is one.
+pub fn get_builtin_template(name: &str) -> Option<&'static BuiltinTemplate> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .find(|template| template.name == name)
+}
+
+// The partial templates that every template can extend, include, or import, as (name, contents)
+// pairs. These are in the `templates/partials` directory, but are named like `cohoard/FILE`.
+static BUILTIN_PARTIALS: &[(&str, &str)] = &[(
+    "cohoard/markdown.css",
+    include_str!("../templates/partials/markdown.css"),
+)];
+
+/// Get the built-in partial templates, as (name, contents) pairs. Every template can use these,
+/// like `{% include "cohoard/markdown.css" %}`.
+pub fn builtin_partials() -> &'static [(&'static str, &'static str)] {
+    BUILTIN_PARTIALS
+}
+
+#[test]
+fn test_validate_config() {
+    let template = r#"
+{#-people
+   [{ "name": "handle", "required": true },
+    { "name": "avatar", "type": "url", "required": true },
+    { "name": "accent", "type": "color" },
+    { "name": "verified", "type": "boolean" }]
+people-#}
+"#;
+    let config = r##"people:
+    - key: JUICE
+      handle: juice
+      avatar: https://example.com/juice.png
+      color: "#ffea02"
+    - key: NINE
+      avatar: nine.png
+      accent: "not a color!"
+      verified: "yes"
+  "##;
+    let config = crate::config::load_config(config).unwrap();
+
+    let problems: Vec<String> = validate_config(template, &config)
+        .unwrap()
+        .iter()
+        .map(|problem| problem.to_string())
+        .collect();
+    assert_eq!(
+        problems,
+        [
+            "NINE.handle: is required, but is missing",
+            "NINE.avatar: should be a URL starting with http://, https://, or data:, but is \"nine.png\"",
+            "NINE.accent: should be a CSS color (like \"#ffea02\"), but is \"not a color!\"",
+            "NINE.verified: should be true or false, but is \"yes\"",
+        ]
+    );
+
+    assert!(validate_config("no schema here", &config)
+        .unwrap()
+        .is_empty());
+    assert!(validate_config("{#-people [{ \"nam\": 1 }] people-#}", &config).is_err());
+
+    assert!(is_color("#fff") && is_color("rgb(1, 2, 3)") && is_color("rebeccapurple"));
+    assert!(!is_color("#ffff0") && !is_color("light blue") && !is_color("yelow"));
+}
+
+#[test]
+fn test_parse_auto_colors() {
+    let template =
+        "{#-auto-colors {% if light_mode %} white {% else %} #36393E {% endif %} auto-colors-#}";
+    let mut context = Context::new();
+    assert_eq!(
+        parse_auto_colors(template, &context).unwrap().as_deref(),
+        Some("#36393e")
+    );
+    context.insert("light_mode", &true);
+    assert_eq!(
+        parse_auto_colors(template, &context).unwrap().as_deref(),
+        Some("#ffffff")
+    );
+    assert_eq!(parse_auto_colors("no block here", &context).unwrap(), None);
+    assert!(parse_auto_colors("{#-auto-colors blurple auto-colors-#}", &context).is_err());
+}
+
+#[test]
+fn test_parse_controls() {
+    let template = r##"
+{#-config
+   [{ "name": "light_mode", "type": "checkbox", "label": "Use Light Mode" },
+    { "name": "enable_barcode", "type": "checkbox", "default": "true" },
+    { "name": "avatar_color", "type": "color", "default": "#43b581" },
+    { "name": "code_theme", "placeholder": "base16-ocean.dark" },
+    { "name": "width", "type": "slider", "default": 5 }]
+config-#}
+{#-config [{ "name": "info", "type": "hidden", "label": "<b>Hi!</b>" }] config-#}
+"##;
+    let controls = parse_controls(template).unwrap();
+    let names: Vec<&str> = controls
+        .iter()
+        .map(|control| control.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        [
+            "light_mode",
+            "enable_barcode",
+            "avatar_color",
+            "code_theme",
+            "width",
+            "info"
+        ]
+    );
+    let kinds: Vec<ControlKind> = controls.iter().map(|control| control.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            ControlKind::Checkbox,
+            ControlKind::Checkbox,
+            ControlKind::Color,
+            ControlKind::Text,
+            ControlKind::Other,
+            ControlKind::Hidden
+        ]
+    );
+    let defaults: Vec<Value> = controls.iter().map(Control::default_value).collect();
+    assert_eq!(
+        defaults,
+        [
+            Value::Bool(false),
+            Value::Bool(true),
+            Value::from("#43b581"),
+            Value::from(""),
+            Value::from("5"),
+            Value::from("")
+        ]
+    );
+    assert_eq!(controls[5].label, "<b>Hi!</b>");
+
+    assert_eq!(
+        controls[0].check_value(Value::Bool(true)),
+        Ok(Value::Bool(true))
+    );
+    assert_eq!(
+        controls[0].check_value(Value::from("yes")).unwrap_err(),
+        "light_mode is a checkbox, so it should be true or false, but is \"yes\""
+    );
+    assert_eq!(
+        controls[4].check_value(Value::from(7)),
+        Ok(Value::from("7"))
+    );
+    assert!(controls[3].check_value(serde_json::json!([1, 2])).is_err());
+
+    assert!(parse_controls("no controls").unwrap().is_empty());
+    assert!(parse_controls("{#-config [{ \"type\": \"text\" }] config-#}").is_err());
+}
+
+#[test]
+fn test_builtin_templates() {
+    assert_eq!(get_builtin_template("discord").unwrap().title, "Discord");
+    assert!(get_builtin_template("nope").is_none());
+    for template in builtin_templates() {
+        parse_controls(template.source).unwrap();
+        parse_person_schema(template.source).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use std::{collections::HashMap, error::Error};
 
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
 use cohoard_rs as cohoard;
@@ -35,20 +36,75 @@ pub fn render(
     cohoard::render(
         template_name,
         template,
+        &[],
         chatlog,
         &config,
         additional_variables.into_iter(),
     )
-    .map_err(|err| {
-        // Try to parse out a Tera error message, if one was encountered during rendering.
-        // TODO: provide more context?
-        if let Some(tera_error) = err.source().and_then(|e| e.downcast_ref::<tera::Error>()) {
-            if let tera::ErrorKind::Msg(msg) = &tera_error.kind {
-                return JsError::new(msg);
-            }
+    .map_err(render_error)
+}
+
+/// Render a chatlog written as JSON, like the chatlogs returned by `export_elements`. Otherwise,
+/// this is the same as `render`.
+#[wasm_bindgen]
+pub fn render_elements(
+    template_name: &str,
+    template: &str,
+    chatlog_json: &str,
+    config: &Config,
+    additional_variables: &JsValue,
+) -> Result<String, JsError> {
+    let config = serde_wasm_bindgen::from_value(config.0.clone())?;
+
+    let additional_variables: HashMap<String, serde_json::Value> =
+        serde_wasm_bindgen::from_value(additional_variables.into())?;
+
+    cohoard::render_elements(
+        template_name,
+        template,
+        &[],
+        chatlog_json,
+        &config,
+        additional_variables.into_iter(),
+    )
+    .map_err(render_error)
+}
+
+fn render_error(err: Box<dyn Error>) -> JsError {
+    // Try to parse out a Tera error message, if one was encountered during rendering.
+    // TODO: provide more context?
+    if let Some(tera_error) = err.source().and_then(|e| e.downcast_ref::<tera::Error>()) {
+        if let tera::ErrorKind::Msg(msg) = &tera_error.kind {
+            return JsError::new(msg);
         }
-        JsError::new(&get_full_msg(err.as_ref()))
-    })
+    }
+    JsError::new(&get_full_msg(err.as_ref()))
+}
+
+/// Parse a chatlog and return it as versioned JSON, which `render_elements` can render. If
+/// `user_keys` is true, people are written as just their key instead of all of their fields.
+#[wasm_bindgen]
+pub fn export_elements(chatlog: &str, config: &Config, user_keys: bool) -> Result<String, JsError> {
+    let config = serde_wasm_bindgen::from_value(config.0.clone())?;
+    let elements = cohoard::parse_posts(&config, chatlog.to_string())
+        .map_err(|err| JsError::new(&get_full_msg(err.as_ref())))?;
+    let style = if user_keys {
+        cohoard::ast::UserStyle::Keys
+    } else {
+        cohoard::ast::UserStyle::Resolved
+    };
+    let chatlog = cohoard::ast::export_elements(&elements, style);
+    Ok(serde_json::to_string_pretty(&chatlog)?)
+}
+
+/// Check the people in the config against the person schema of the template. Returns an array of
+/// `{ person, field, message }` objects, one for each problem found.
+#[wasm_bindgen]
+pub fn validate_config(template: &str, config: &Config) -> Result<JsValue, JsError> {
+    let config = serde_wasm_bindgen::from_value(config.0.clone())?;
+    let problems = cohoard::template::validate_config(template, &config)
+        .map_err(|err| JsError::new(&get_full_msg(err.as_ref())))?;
+    Ok(serde_wasm_bindgen::to_value(&problems)?)
 }
 
 #[wasm_bindgen]
@@ -59,3 +115,42 @@ pub fn load_config(config: &str) -> Result<Config, JsError> {
     let config = serde_wasm_bindgen::to_value(&config)?;
     Ok(Config(config))
 }
+
+#[wasm_bindgen]
+pub fn load_config_as(config: &str, format: &str) -> Result<Config, JsError> {
+    let format: cohoard::config::ConfigFormat =
+        format.parse().map_err(|err: String| JsError::new(&err))?;
+    let config = cohoard::config::load_config_as(config, format)
+        .map_err(|err| JsError::new(&get_full_msg(err.as_ref())))?;
+
+    let config = serde_wasm_bindgen::to_value(&config)?;
+    Ok(Config(config))
+}
+
+#[wasm_bindgen]
+pub fn save_config(config: &Config, format: &str) -> Result<String, JsError> {
+    let format: cohoard::config::ConfigFormat =
+        format.parse().map_err(|err: String| JsError::new(&err))?;
+    let config = serde_wasm_bindgen::from_value(config.0.clone())?;
+    cohoard::config::save_config(&config, format)
+        .map_err(|err| JsError::new(&get_full_msg(err.as_ref())))
+}
+
+/// Get the built-in presets, as an object mapping each preset's name to its fields.
+#[wasm_bindgen]
+pub fn presets() -> Result<JsValue, JsError> {
+    let presets: HashMap<&str, &cohoard::User> = cohoard::config::presets()
+        .iter()
+        .map(|preset| (preset.name.as_str(), &preset.user))
+        .collect();
+    Ok(presets.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// Get the controls of a template, as an array of `{ name, type, label, default, placeholder }`
+/// objects.
+#[wasm_bindgen]
+pub fn parse_controls(template: &str) -> Result<JsValue, JsError> {
+    let controls = cohoard::template::parse_controls(template)
+        .map_err(|err| JsError::new(&get_full_msg(err.as_ref())))?;
+    Ok(controls.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
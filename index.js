@@ -3,11 +3,15 @@ import init, * as cohoard_module from "https://static.witchoflight.com/~a2aaron/
 import { ConfigTable } from "./config_table.js"
 import { TemplateControls, DISCORD_BUILTIN } from "./template_controls.js";
 import { getTypedElementById, localStorageOrDefault, render_error_messages, try_parse_tera_error } from "./util.js";
-import { PRESETS } from "./presets.js"
+import { PRESETS as FALLBACK_PRESETS } from "./presets.js"
 
 /** @type {typeof cohoard_module?} */
 let cohoard = null;
 
+/** The presets for the "Add person" dropdown. This is replaced by cohoard's presets once it loads.
+ * @type {{[key: string]: object}} */
+let PRESETS = FALLBACK_PRESETS;
+
 // Render the chat log to the preview/HTML areas using the
 // currently selected template.
 export function render() {
@@ -16,8 +20,18 @@ export function render() {
       return;
    }
 
-   let html_output = template_controls.get_current_template().render(config_table.cohoard_config, script_textarea.value);
    let errors = [];
+   let cohoard_config;
+   try {
+      cohoard_config = config_table.cohoard_config;
+   } catch (err) {
+      // The config is invalid, such as if it has a misspelled color.
+      console.error("Failed to load config\n", err);
+      render_error_messages(err);
+      return;
+   }
+
+   let html_output = template_controls.get_current_template().render(cohoard_config, script_textarea.value);
 
    if (typeof (html_output) === "string") {
       preview_area.innerHTML = html_output;
@@ -215,6 +229,12 @@ try {
 }
 
 cohoard = cohoard_module;
+// Older versions of cohoard don't have a preset registry.
+// @ts-ignore
+if (cohoard.presets) {
+   // @ts-ignore
+   PRESETS = cohoard.presets();
+}
 
 function after_cohoard_load() {
    render();
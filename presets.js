@@ -1,3 +1,6 @@
+// This file is generated from cohoard/cohoard_rs/src/presets.yaml, so don't edit it by hand.
+// Run `UPDATE_PRESETS_JS=1 cargo test` in cohoard/cohoard_rs to update it. It's only used with
+// versions of cohoard that don't have the preset registry.
 /** @type {{[key: string]: object}} */
 export const PRESETS = {
     // COHOST AVATARS
@@ -6,14 +9,14 @@ export const PRESETS = {
         name: "egg bug!",
         color: "#83254f",
         avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/eggbug.png",
-        handle: "eggbug"
+        handle: "eggbug",
     },
     "cohost-bugegg": {
         key: "BUGEGG",
         name: "bug egg >:3",
         color: "#258359",
         avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/bugegg.png",
-        handle: "bugegg"
+        handle: "bugegg",
     },
     // CORRU.OBSERVER
     "corru-interloper": {
@@ -416,50 +419,50 @@ export const PRESETS = {
         key: "BLACK",
         name: "Black",
         color: "black",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-black.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-black.png",
     },
     "utility-white": {
         key: "WHITE",
         name: "White",
         color: "white",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-white.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-white.png",
     },
     "utility-transparent": {
         key: "TRANSPARENT",
         name: "Transparent",
         color: "transparent",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-transparent.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/utility-transparent.png",
     },
     // DISCORD DEFAULT AVATARS
     "discord-red": {
         key: "RED",
         name: "Discord Red",
         color: "#DB504C",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-red.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-red.png",
     },
     "discord-yellow": {
         key: "YELLOW",
         name: "Discord Yellow",
         color: "#EEAA41",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-yellow.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-yellow.png",
     },
     "discord-green": {
         key: "GREEN",
         name: "Discord Green",
         color: "#5AA363",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-green.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-green.png",
     },
     "discord-blue": {
         key: "BLUE",
         name: "Discord Blue",
         color: "#5A65EA",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-blue.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-blue.png",
     },
     "discord-grey": {
         key: "GREY",
         name: "Discord Grey",
         color: "#777E89",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-grey.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/discord-grey.png",
     },
     // TWITTER DEFAULT AVATARS
     "twitter-person-grey": {
@@ -467,55 +470,55 @@ export const PRESETS = {
         name: "Grey",
         handle: "person_grey",
         color: "#CED6DC",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-person-grey.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-person-grey.png",
     },
     "twitter-egg-red": {
         key: "RED",
         name: "Egg Red",
         handle: "egg_red",
         color: "#CE3A60",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-red.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-red.png",
     },
     "twitter-egg-orange": {
         key: "ORANGE",
         name: "Egg Orange",
         handle: "egg_orange",
         color: "#E26737",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-orange.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-orange.png",
     },
     "twitter-egg-yellow": {
         key: "YELLOW",
         name: "Egg Yellow",
         handle: "egg_yellow",
         color: "#F3B145",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-yellow.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-yellow.png",
     },
     "twitter-egg-green": {
         key: "GREEN",
         name: "Egg Green",
         handle: "egg_green",
         color: "#59BC6D",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-green.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-green.png",
     },
     "twitter-egg-blue": {
         key: "BLUE",
         name: "Egg Blue",
         handle: "egg_blue",
         color: "#4D9FEB",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-blue.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-blue.png",
     },
     "twitter-egg-purple": {
         key: "PURPLE",
         name: "Egg Purple",
         handle: "egg_purple",
         color: "#724DBD",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-purple.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-purple.png",
     },
     "twitter-egg-grey": {
         key: "GREY",
         name: "Egg Grey",
         handle: "egg_grey",
         color: "#8B98A5",
-        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-grey.png"
+        avatar: "https://static.witchoflight.com/~a2aaron/cohoard-avatars/twitter-egg-grey.png",
     },
-};
\ No newline at end of file
+};
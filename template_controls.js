@@ -752,12 +752,12 @@ async function get_template_from_url(url) {
     return text;
 }
 
-export const DISCORD_BUILTIN = await Template.builtin("Discord", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/discord.html", "discord");
-export const TWITTER_BUILTIN = await Template.builtin("Twitter", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/twitter.html", "twitter");
-export const FOOTBALL_BUILTIN = await Template.builtin("17776", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/17776.html", "17776");
-export const HOMESTUCK_BUILTIN = await Template.builtin("MSPA", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/homestuck.html", "homestuck");
-export const UNDERTALE_BUILTIN = await Template.builtin("Undertale", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/undertale.html", "undertale");
-export const WIKIPEDIA_BUILTIN = await Template.builtin("Wikipedia", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/wikipedia.html", "wikipedia");
-export const CHATGPT_BUILTIN = await Template.builtin("ChatGPT", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/chatgpt.html", "wikipedia");
-export const CORRU_BUILTIN = await Template.builtin("corru.observer", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/corru_observer.html", "corru-observer");
-export const BASIC_TEMPLATE = await get_template_from_url("https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/templates/basic.html") ?? "Couldn't fetch template!";
+export const DISCORD_BUILTIN = await Template.builtin("Discord", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/discord.html", "discord");
+export const TWITTER_BUILTIN = await Template.builtin("Twitter", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/twitter.html", "twitter");
+export const FOOTBALL_BUILTIN = await Template.builtin("17776", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/17776.html", "17776");
+export const HOMESTUCK_BUILTIN = await Template.builtin("MSPA", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/homestuck.html", "homestuck");
+export const UNDERTALE_BUILTIN = await Template.builtin("Undertale", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/undertale.html", "undertale");
+export const WIKIPEDIA_BUILTIN = await Template.builtin("Wikipedia", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/wikipedia.html", "wikipedia");
+export const CHATGPT_BUILTIN = await Template.builtin("ChatGPT", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/chatgpt.html", "wikipedia");
+export const CORRU_BUILTIN = await Template.builtin("corru.observer", "https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/corru_observer.html", "corru-observer");
+export const BASIC_TEMPLATE = await get_template_from_url("https://raw.githubusercontent.com/a2aaron/Cohoard/gh-pages/cohoard/cohoard_rs/templates/basic.html") ?? "Couldn't fetch template!";